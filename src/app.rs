@@ -1,24 +1,173 @@
-use egui::Color32;
+use egui::{
+    text::{LayoutJob, TextFormat},
+    Color32,
+};
 use winit::{event::WindowEvent, window::WindowId};
 
-use crate::color::Color;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    camera::{Camera, CameraControls},
+    channels::{self, ChannelInput, Filter, Wrap, CHANNEL_COUNT},
+    color::Color,
+    globals::Mouse,
+    overrides::{Override, OverrideValue},
+    post::{self, PostPass},
+    preprocess::Defines,
+    session::{self, SavedPostPass, Session},
+    shader::ShaderError,
+    uniforms::{Control, UniformBlock, UniformField, UniformValue, Widget},
+};
+
+/// How many lines of source to show either side of a shader error.
+const ERROR_CONTEXT_LINES: u32 = 3;
 
 pub struct App {
     pub bg_color: Color,
-    pub triangle_color: Color,
-    pub blur_kernel: u8,
+    /// Values for the shader's reflected uniform, shown as controls in the UI.
+    pub uniforms: UniformBlock,
+    /// The names the shader's `#ifdef`s test, shown as checkboxes.
+    pub switches: Vec<String>,
+    /// The switches that are on, picking which permutation of the shader runs.
+    pub defines: Defines,
+    /// Values for the shader's `override`s. Changing one rebuilds the
+    /// pipeline.
+    pub overrides: Vec<Override>,
+    pub mouse: Mouse,
+    pub camera: Camera,
+    pub camera_controls: CameraControls,
+    /// Where the session is saved, see `Session`.
+    pub session_path: PathBuf,
+    pub post_passes: Vec<PostPass>,
+    pub post_dir: PathBuf,
+    /// Shaders in `post_dir` that can be added to `post_passes`.
+    pub post_shaders: Vec<PathBuf>,
+    /// The images and audio on the shader's channels.
+    pub channels: Vec<ChannelInput>,
+    /// What the pass graph puts on the first channels, which takes the place
+    /// of a file.
+    pub graph_channels: Vec<String>,
+    /// The shader's directory, where channel files are picked from.
+    pub channel_dir: PathBuf,
+    /// Files in `channel_dir` that can go on a channel.
+    pub channel_files: Vec<PathBuf>,
+    pub shader_error: Option<ShaderError>,
+    /// Whether shaders are building in the background.
+    pub compiling: bool,
+}
+
+/// An edit to the post chain picked in the UI, applied once the list isn't
+/// borrowed anymore.
+enum PostEdit {
+    MoveUp(usize),
+    MoveDown(usize),
+    Remove(usize),
+    Add(PathBuf),
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(shader: &Path) -> Self {
+        let post_dir = post::shader_dir(shader);
+        let channel_dir = shader.parent().unwrap_or(Path::new(".")).to_owned();
         Self {
             bg_color: Color(palette::LinSrgba::from_components((0.1, 0.1, 0.1, 1.0))),
-            triangle_color: Color32::BLUE.into(),
-            blur_kernel: 0,
+            uniforms: UniformBlock::default(),
+            switches: vec![],
+            defines: Defines::new(),
+            overrides: vec![],
+            mouse: Mouse::default(),
+            camera: Camera::default(),
+            camera_controls: CameraControls::default(),
+            session_path: session::session_path(shader),
+            post_passes: post::default_chain(&post_dir),
+            post_shaders: post::available_shaders(&post_dir),
+            post_dir,
+            channels: vec![ChannelInput::default(); CHANNEL_COUNT as usize],
+            graph_channels: vec![],
+            channel_files: channels::available_files(&channel_dir),
+            channel_dir,
+            shader_error: None,
+            compiling: false,
+        }
+    }
+
+    /// Feed `event` to the mouse and the camera. The camera ignores what the
+    /// UI `consumed`.
+    pub fn handle_window_event(
+        &mut self,
+        _window_id: WindowId,
+        event: &WindowEvent,
+        consumed: bool,
+    ) {
+        self.mouse.handle_window_event(event);
+        self.camera_controls
+            .handle_window_event(&mut self.camera, event, consumed);
+    }
+
+    /// Pick up the session at `session_path`, if there is one. Uniform values
+    /// and overrides are carried over once the shader is built.
+    pub fn load_session(&mut self) -> Result<(), ShaderError> {
+        let Some(session) = Session::load(&self.session_path)? else {
+            return Ok(());
+        };
+        println!("Loaded session {}", self.session_path.display());
+        self.defines = session.defines;
+        self.uniforms = UniformBlock::from_values(session.uniforms);
+        self.overrides = session
+            .overrides
+            .into_iter()
+            .map(|(name, value)| Override { name, value })
+            .collect();
+        for (input, saved) in self.channels.iter_mut().zip(session.channels) {
+            *input = ChannelInput {
+                file: saved.file.map(|file| self.channel_dir.join(file)),
+                ..saved
+            };
         }
+        if let Some(post) = session.post {
+            self.post_passes = post
+                .into_iter()
+                .map(|pass| PostPass {
+                    name: pass.name,
+                    shader: self.post_dir.join(pass.shader),
+                    enabled: pass.enabled,
+                    params: UniformBlock::from_values(pass.params),
+                })
+                .collect();
+        }
+        self.camera = session.camera;
+        Ok(())
     }
 
-    pub fn handle_window_event(&mut self, _window_id: WindowId, _event: &WindowEvent) {}
+    pub fn save_session(&self) -> color_eyre::Result<()> {
+        let relative = |path: &Path, dir: &Path| path.strip_prefix(dir).unwrap_or(path).to_owned();
+        let channels = self.channels.iter().map(|input| ChannelInput {
+            file: input
+                .file
+                .as_deref()
+                .map(|file| relative(file, &self.channel_dir)),
+            ..input.clone()
+        });
+        let post = self.post_passes.iter().map(|pass| SavedPostPass {
+            name: pass.name.clone(),
+            shader: relative(&pass.shader, &self.post_dir),
+            enabled: pass.enabled,
+            params: pass.params.values(),
+        });
+        let session = Session {
+            defines: self.defines.clone(),
+            uniforms: self.uniforms.values(),
+            overrides: self
+                .overrides
+                .iter()
+                .map(|o| (o.name.clone(), o.value))
+                .collect(),
+            channels: channels.collect(),
+            post: Some(post.collect()),
+            camera: self.camera,
+        };
+        session.save(&self.session_path)
+    }
 
     pub fn ui(&mut self, ctx: &egui::Context) {
         egui::Window::new("stuff")
@@ -32,12 +181,17 @@ impl App {
                     .spacing([40.0, 4.0])
                     .striped(true)
                     .show(ui, |ui| {
-                        ui.label("triangle color");
-                        let mut color = self.triangle_color.into();
-                        if ui.color_edit_button_srgba(&mut color).changed() {
-                            self.triangle_color = color.into();
-                        };
-                        ui.end_row();
+                        for field in &mut self.uniforms.fields {
+                            ui.label(&field.name);
+                            uniform_ui(ui, field);
+                            ui.end_row();
+                        }
+
+                        for o in &mut self.overrides {
+                            ui.label(&o.name);
+                            override_ui(ui, &mut o.value);
+                            ui.end_row();
+                        }
 
                         ui.label("bg color");
                         let mut color = self.bg_color.into();
@@ -45,11 +199,289 @@ impl App {
                             self.bg_color = color.into();
                         };
                         ui.end_row();
+                    });
 
-                        ui.label("Blur Kernel Size");
-                        ui.add(egui::DragValue::new(&mut self.blur_kernel).clamp_range(0..=120));
-                        ui.end_row();
+                for warning in &self.uniforms.warnings {
+                    ui.colored_label(ui.visuals().warn_fg_color, warning);
+                }
+
+                if !self.switches.is_empty() {
+                    ui.separator();
+                    for name in &self.switches {
+                        let mut on = self.defines.contains(name);
+                        if ui.checkbox(&mut on, name).changed() {
+                            if on {
+                                self.defines.insert(name.clone());
+                            } else {
+                                self.defines.remove(name);
+                            }
+                        }
+                    }
+                }
+
+                ui.separator();
+                ui.collapsing("channels", |ui| self.channels_ui(ui));
+                ui.collapsing("camera", |ui| self.camera_ui(ui));
+
+                ui.separator();
+                if ui.button("save session").clicked() {
+                    match self.save_session() {
+                        Ok(()) => println!("Saved {}", self.session_path.display()),
+                        Err(err) => eprintln!("Can't save {}: {err}", self.session_path.display()),
+                    }
+                }
+            });
+
+        egui::Window::new("post processing")
+            .anchor(egui::Align2::LEFT_BOTTOM, [0.0, 0.0])
+            .resizable(true)
+            .default_width(280.0)
+            .show(ctx, |ui| self.post_ui(ui));
+
+        if self.compiling {
+            egui::Area::new("compiling")
+                .anchor(egui::Align2::RIGHT_BOTTOM, [-8.0, -8.0])
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("compiling…");
                     });
+                });
+        }
+
+        if let Some(err) = &self.shader_error {
+            egui::Window::new("shader error")
+                .anchor(egui::Align2::RIGHT_TOP, [0.0, 0.0])
+                .resizable(true)
+                .default_width(480.0)
+                .show(ctx, |ui| shader_error_ui(ui, err));
+        }
+    }
+
+    fn camera_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("drag to orbit, right-drag to look, scroll to zoom, WASD and QE to fly");
+        ui.add(egui::Slider::new(&mut self.camera.fov, 10.0..=150.0).text("fov"));
+        if ui.button("reset").clicked() {
+            self.camera = Camera::default();
+        }
+    }
+
+    fn channels_ui(&mut self, ui: &mut egui::Ui) {
+        for (i, input) in self.channels.iter_mut().enumerate() {
+            ui.push_id(i, |ui| {
+                if let Some(name) = self.graph_channels.get(i) {
+                    ui.label(format!("channel {i}: {name}"));
+                    return;
+                }
+                let selected = match &input.file {
+                    Some(path) => path.file_name().unwrap_or_default().to_string_lossy(),
+                    None => "none".into(),
+                };
+                egui::ComboBox::from_label(format!("channel {i}"))
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut input.file, None, "none");
+                        for path in &self.channel_files {
+                            let name = path.file_name().unwrap_or_default().to_string_lossy();
+                            ui.selectable_value(&mut input.file, Some(path.clone()), name);
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    let sampling = &mut input.sampling;
+                    egui::ComboBox::from_id_source("filter")
+                        .selected_text(sampling.filter.name())
+                        .show_ui(ui, |ui| {
+                            for filter in Filter::ALL {
+                                ui.selectable_value(&mut sampling.filter, filter, filter.name());
+                            }
+                        });
+                    egui::ComboBox::from_id_source("wrap")
+                        .selected_text(sampling.wrap.name())
+                        .show_ui(ui, |ui| {
+                            for wrap in Wrap::ALL {
+                                ui.selectable_value(&mut sampling.wrap, wrap, wrap.name());
+                            }
+                        });
+                    ui.checkbox(&mut input.flip, "flip");
+                    ui.checkbox(&mut input.srgb, "sRGB");
+                });
             });
+        }
+    }
+
+    fn post_ui(&mut self, ui: &mut egui::Ui) {
+        let mut edit = None;
+        let count = self.post_passes.len();
+        for (i, pass) in self.post_passes.iter_mut().enumerate() {
+            ui.push_id(i, |ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut pass.enabled, &pass.name);
+                    if ui.add_enabled(i > 0, egui::Button::new("⬆")).clicked() {
+                        edit = Some(PostEdit::MoveUp(i));
+                    }
+                    if ui
+                        .add_enabled(i + 1 < count, egui::Button::new("⬇"))
+                        .clicked()
+                    {
+                        edit = Some(PostEdit::MoveDown(i));
+                    }
+                    if ui.button("🗑").clicked() {
+                        edit = Some(PostEdit::Remove(i));
+                    }
+                });
+                egui::Grid::new("params").num_columns(2).show(ui, |ui| {
+                    for field in &mut pass.params.fields {
+                        ui.label(&field.name);
+                        uniform_ui(ui, field);
+                        ui.end_row();
+                    }
+                });
+                for warning in &pass.params.warnings {
+                    ui.colored_label(ui.visuals().warn_fg_color, warning);
+                }
+            });
+            ui.separator();
+        }
+
+        egui::ComboBox::from_id_source("add post pass")
+            .selected_text("add pass")
+            .show_ui(ui, |ui| {
+                for path in &self.post_shaders {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    if ui.selectable_label(false, name).clicked() {
+                        edit = Some(PostEdit::Add(path.clone()));
+                    }
+                }
+            });
+
+        match edit {
+            Some(PostEdit::MoveUp(i)) => self.post_passes.swap(i - 1, i),
+            Some(PostEdit::MoveDown(i)) => self.post_passes.swap(i, i + 1),
+            Some(PostEdit::Remove(i)) => {
+                self.post_passes.remove(i);
+            }
+            Some(PostEdit::Add(path)) => self.post_passes.push(PostPass::new(path)),
+            None => {}
+        }
+    }
+}
+
+fn uniform_ui(ui: &mut egui::Ui, field: &mut UniformField) {
+    let control = &field.control;
+    match (&mut field.value, control.widget) {
+        (UniformValue::Vec3(rgb), Widget::Color) => {
+            ui.color_edit_button_rgb(rgb);
+        }
+        (UniformValue::Vec4(rgba), Widget::Color) => {
+            ui.color_edit_button_rgba_unmultiplied(rgba);
+        }
+        (UniformValue::U32(x), Widget::Checkbox) => {
+            let mut on = *x != 0;
+            if ui.checkbox(&mut on, "").changed() {
+                *x = on as u32;
+            }
+        }
+        (UniformValue::I32(x), Widget::Checkbox) => {
+            let mut on = *x != 0;
+            if ui.checkbox(&mut on, "").changed() {
+                *x = on as i32;
+            }
+        }
+        (UniformValue::F32(x), _) => number_ui(ui, x, control),
+        (UniformValue::I32(x), _) => number_ui(ui, x, control),
+        (UniformValue::U32(x), _) => number_ui(ui, x, control),
+        (UniformValue::Vec2(v), _) => vector_ui(ui, v, control),
+        (UniformValue::Vec3(v), _) => vector_ui(ui, v, control),
+        (UniformValue::Vec4(v), _) => vector_ui(ui, v, control),
+        (UniformValue::Unsupported(ty), _) => {
+            ui.weak(format!("no control for {ty}"));
+        }
+    }
+}
+
+fn override_ui(ui: &mut egui::Ui, value: &mut OverrideValue) {
+    match value {
+        OverrideValue::Bool(x) => ui.checkbox(x, ""),
+        OverrideValue::I32(x) => ui.add(egui::DragValue::new(x)),
+        OverrideValue::U32(x) => ui.add(egui::DragValue::new(x)),
+        OverrideValue::F32(x) => ui.add(egui::DragValue::new(x).speed(0.01)),
+    };
+}
+
+fn vector_ui(ui: &mut egui::Ui, values: &mut [f32], control: &Control) {
+    ui.horizontal(|ui| {
+        for x in values {
+            number_ui(ui, x, control);
+        }
+    });
+}
+
+fn number_ui<N: egui::emath::Numeric>(ui: &mut egui::Ui, x: &mut N, control: &Control) {
+    let range = control
+        .range
+        .as_ref()
+        .map(|range| N::from_f64(*range.start())..=N::from_f64(*range.end()));
+    match (control.widget, range) {
+        (Widget::Slider, Some(range)) => {
+            ui.add(egui::Slider::new(x, range));
+        }
+        (_, range) => {
+            let mut drag = egui::DragValue::new(x).speed(control.step);
+            if let Some(range) = range {
+                drag = drag.clamp_range(range);
+            }
+            ui.add(drag);
+        }
+    }
+}
+
+fn shader_error_ui(ui: &mut egui::Ui, err: &ShaderError) {
+    let heading = match err.location {
+        Some(loc) => format!("{}:{}:{}", err.path.display(), loc.line, loc.column),
+        None => err.path.display().to_string(),
+    };
+    ui.strong(heading);
+    ui.colored_label(ui.visuals().error_fg_color, &err.message);
+
+    let excerpt = err.excerpt(ERROR_CONTEXT_LINES);
+    if excerpt.is_empty() {
+        return;
+    }
+    let font = egui::TextStyle::Monospace.resolve(ui.style());
+    let plain = TextFormat::simple(font.clone(), ui.visuals().text_color());
+    let gutter = TextFormat::simple(font.clone(), ui.visuals().weak_text_color());
+    let marked = TextFormat {
+        background: ui.visuals().error_fg_color,
+        ..TextFormat::simple(font, Color32::WHITE)
+    };
+
+    let mut job = LayoutJob::default();
+    for line in excerpt {
+        job.append(&format!("{:>4} | ", line.number), 0.0, gutter.clone());
+        let text = line.text;
+        let parts = line.highlight.and_then(|range| {
+            // A marker past the end of the line covers a space.
+            let span = match text.get(range.clone()) {
+                Some(span) => span,
+                None if range.start == text.len() => " ",
+                None => return None,
+            };
+            Some((
+                text.get(..range.start)?,
+                span,
+                text.get(range.end..).unwrap_or_default(),
+            ))
+        });
+        match parts {
+            Some((before, span, after)) => {
+                job.append(before, 0.0, plain.clone());
+                job.append(span, 0.0, marked.clone());
+                job.append(after, 0.0, plain.clone());
+            }
+            None => job.append(text, 0.0, plain.clone()),
+        }
+        job.append("\n", 0.0, plain.clone());
     }
+    ui.separator();
+    ui.label(job);
 }
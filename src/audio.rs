@@ -0,0 +1,228 @@
+use std::{path::Path, sync::Arc};
+
+use realfft::{RealFftPlanner, RealToComplex};
+
+use crate::shader::ShaderError;
+
+/// The width of an audio texture: how many frequency bins and waveform
+/// samples it holds, like Shadertoy's.
+pub const AUDIO_TEXTURE_WIDTH: u32 = 512;
+
+/// Samples per analysis window, as in the Web Audio analyser Shadertoy uses.
+/// Only the lowest quarter of the spectrum makes it into the texture.
+const FFT_SIZE: usize = 2048;
+
+/// The levels that map to 0 and 1 in the spectrum, Web Audio's defaults.
+const MIN_DECIBELS: f32 = -100.0;
+const MAX_DECIBELS: f32 = -30.0;
+
+/// How much of the previous frame's spectrum is kept, Web Audio's default.
+const SMOOTHING: f32 = 0.8;
+
+/// A WAV file that plays along with the frame clock, analysed every frame for
+/// an audio texture. The texture is `AUDIO_TEXTURE_WIDTH` by 2 with one
+/// channel: the first row is the spectrum from low to high frequencies, the
+/// second the waveform of the most recent samples, 0.5 being silence. There's
+/// no sound; past the end of the file the track is silent.
+pub struct AudioTrack {
+    /// Mixed down to mono, from -1 to 1.
+    samples: Vec<f32>,
+    sample_rate: u32,
+    fft: Arc<dyn RealToComplex<f32>>,
+    /// The Blackman window applied before the FFT.
+    window: Vec<f32>,
+    /// The smoothed magnitudes of the previous frame.
+    magnitudes: Vec<f32>,
+    /// The time of the previous frame, `None` before the first.
+    last_time: Option<f32>,
+}
+
+impl AudioTrack {
+    pub fn load(path: &Path) -> Result<Self, ShaderError> {
+        let error = |err: hound::Error| ShaderError::new(path, format!("can't load audio: {err}"));
+        let mut reader = hound::WavReader::open(path).map_err(error)?;
+        let spec = reader.spec();
+        let interleaved: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 * scale))
+                    .collect()
+            }
+        }
+        .map_err(error)?;
+        let channels = spec.channels.max(1) as usize;
+        let samples = interleaved
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let x = std::f32::consts::TAU * i as f32 / FFT_SIZE as f32;
+                0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos()
+            })
+            .collect();
+        Ok(Self {
+            samples,
+            sample_rate: spec.sample_rate,
+            fft: RealFftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            magnitudes: vec![0.0; AUDIO_TEXTURE_WIDTH as usize],
+            last_time: None,
+        })
+    }
+
+    /// The texture's rows for the window of samples ending at `time` seconds.
+    /// The spectrum is smoothed over the frames that lead up to it, starting
+    /// over on the first frame and whenever time doesn't move forward, so a
+    /// single frame or a seek doesn't depend on what was analysed before.
+    pub fn analyse(&mut self, time: f32) -> Vec<u8> {
+        let smooth = self.last_time.is_some_and(|last| time > last);
+        self.last_time = Some(time);
+        let end = (time.max(0.0) as f64 * self.sample_rate as f64) as usize;
+        let sample = |i: usize| {
+            let index = (end + i).checked_sub(FFT_SIZE)?;
+            self.samples.get(index).copied()
+        };
+        let recent: Vec<f32> = (0..FFT_SIZE).map(|i| sample(i).unwrap_or(0.0)).collect();
+
+        let mut input: Vec<f32> = recent
+            .iter()
+            .zip(&self.window)
+            .map(|(s, w)| s * w)
+            .collect();
+        let mut spectrum = self.fft.make_output_vec();
+        self.fft
+            .process(&mut input, &mut spectrum)
+            .expect("the buffers are the size the FFT was planned for");
+
+        let width = AUDIO_TEXTURE_WIDTH as usize;
+        let mut texture = Vec::with_capacity(2 * width);
+        for (smoothed, bin) in self.magnitudes.iter_mut().zip(&spectrum) {
+            let magnitude = bin.norm() / FFT_SIZE as f32;
+            *smoothed = if smooth {
+                SMOOTHING * *smoothed + (1.0 - SMOOTHING) * magnitude
+            } else {
+                magnitude
+            };
+            texture.push(decibel_byte(*smoothed));
+        }
+        texture.extend(
+            recent[FFT_SIZE - width..]
+                .iter()
+                .map(|sample| to_byte(0.5 + 0.5 * sample)),
+        );
+        texture
+    }
+}
+
+/// A magnitude as a byte of the spectrum: its level in decibels, with
+/// `MIN_DECIBELS` and below at 0 and `MAX_DECIBELS` and above at 255.
+fn decibel_byte(magnitude: f32) -> u8 {
+    let decibels = 20.0 * magnitude.log10();
+    to_byte((decibels - MIN_DECIBELS) / (MAX_DECIBELS - MIN_DECIBELS))
+}
+
+/// 0 to 1 as a byte, clamped.
+fn to_byte(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A magnitude `decibels` loud.
+    fn magnitude(decibels: f32) -> f32 {
+        10f32.powf(decibels / 20.0)
+    }
+
+    #[test]
+    fn decibels_map_onto_the_byte_range() {
+        assert_eq!(decibel_byte(magnitude(MAX_DECIBELS)), 255);
+        assert_eq!(decibel_byte(magnitude(MIN_DECIBELS)), 0);
+        assert_eq!(decibel_byte(magnitude(-65.0)), 128);
+        assert_eq!(decibel_byte(magnitude(-51.0)), 179);
+    }
+
+    #[test]
+    fn levels_outside_the_range_are_clamped() {
+        assert_eq!(decibel_byte(1.0), 255);
+        assert_eq!(decibel_byte(magnitude(-140.0)), 0);
+        assert_eq!(decibel_byte(0.0), 0);
+    }
+
+    /// A mono 16-bit WAV of a sine at `frequency` Hz and `amplitude` from 0
+    /// to 1, or silence at 0 Hz.
+    fn write_sine(path: &Path, frequency: f32, amplitude: f32, sample_rate: u32, seconds: f32) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for i in 0..(seconds * sample_rate as f32) as u32 {
+            let phase = std::f32::consts::TAU * frequency * i as f32 / sample_rate as f32;
+            writer
+                .write_sample((amplitude * phase.sin() * i16::MAX as f32) as i16)
+                .unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn a_sine_peaks_in_its_bin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sine.wav");
+        // Bin 40 of a 2048 sample FFT at 48 kHz.
+        write_sine(&path, 937.5, 0.01, 48000, 1.0);
+        let mut track = AudioTrack::load(&path).unwrap();
+        let texture = track.analyse(0.5);
+        assert_eq!(texture.len(), 2 * AUDIO_TEXTURE_WIDTH as usize);
+
+        let spectrum = &texture[..AUDIO_TEXTURE_WIDTH as usize];
+        let peak = (0..spectrum.len()).max_by_key(|&i| spectrum[i]).unwrap();
+        assert_eq!(peak, 40);
+        assert!(spectrum[200] < spectrum[40] / 2);
+    }
+
+    #[test]
+    fn a_steady_sine_looks_the_same_however_it_was_reached() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sine.wav");
+        write_sine(&path, 937.5, 0.01, 48000, 1.0);
+        let single = AudioTrack::load(&path).unwrap().analyse(0.5);
+
+        // Playing up to it settles on the same spectrum.
+        let mut played = AudioTrack::load(&path).unwrap();
+        let mut texture = vec![];
+        for frame in 0..=30 {
+            texture = played.analyse(0.2 + 0.01 * frame as f32);
+        }
+        let close = |a: &[u8], b: &[u8]| a.iter().zip(b).all(|(a, b)| a.abs_diff(*b) <= 2);
+        assert!(close(&texture, &single));
+
+        // Seeking back starts over rather than blending in later frames.
+        played.analyse(0.9);
+        assert_eq!(played.analyse(0.5), single);
+    }
+
+    #[test]
+    fn silence_is_an_empty_spectrum_and_a_flat_waveform() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("silence.wav");
+        write_sine(&path, 0.0, 0.5, 44100, 0.5);
+        let mut track = AudioTrack::load(&path).unwrap();
+        // Past the end of the file is silent too.
+        for time in [0.25, 10.0] {
+            let texture = track.analyse(time);
+            let (spectrum, waveform) = texture.split_at(AUDIO_TEXTURE_WIDTH as usize);
+            assert!(spectrum.iter().all(|&level| level == 0));
+            assert!(waveform.iter().all(|&sample| sample == 128));
+        }
+    }
+}
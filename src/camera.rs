@@ -0,0 +1,362 @@
+use std::collections::HashSet;
+
+use glam::{Mat4, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+/// Radians the camera turns per pixel dragged.
+const TURN_SPEED: f32 = 0.005;
+
+/// How much one line of scrolling moves the camera in or out.
+const ZOOM_STEP: f32 = 1.1;
+
+/// Pixels of touchpad scrolling that count as one line.
+const PIXELS_PER_LINE: f32 = 40.0;
+
+/// Pitch stops short of straight up or down, where the view would flip.
+const MAX_PITCH: f32 = 1.55;
+
+/// Where the camera is and how it sees. It looks at `target` from `distance`
+/// away, turned `yaw` radians around the y axis from looking down -z and
+/// `pitch` radians down. Saved with the session, see `Session`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Camera {
+    pub target: Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// The vertical field of view in degrees.
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            target: Vec3::ZERO,
+            distance: 3.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 60.0,
+            near: 0.01,
+            far: 1000.0,
+        }
+    }
+}
+
+impl Camera {
+    /// The direction the camera looks in.
+    pub fn forward(&self) -> Vec3 {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        Vec3::new(-sin_yaw * cos_pitch, -sin_pitch, -cos_yaw * cos_pitch)
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.target - self.forward() * self.distance
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.position(), self.target, Vec3::Y)
+    }
+
+    /// The projection for a target `aspect` times as wide as it's high, with
+    /// depth from 0 at `near` to 1 at `far`.
+    pub fn projection(&self, aspect: f32) -> Mat4 {
+        Mat4::perspective_rh(self.fov.to_radians(), aspect, self.near, self.far)
+    }
+
+    /// Turn around the target, which stays put, for a drag of `dx` by `dy`
+    /// pixels.
+    fn orbit(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * TURN_SPEED;
+        self.pitch = (self.pitch + dy * TURN_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Turn in place, moving the target around the camera.
+    fn look(&mut self, dx: f32, dy: f32) {
+        let position = self.position();
+        self.orbit(dx, dy);
+        self.target = position + self.forward() * self.distance;
+    }
+
+    /// Move towards the target, or away for negative `lines`.
+    fn zoom(&mut self, lines: f32) {
+        self.distance = (self.distance * ZOOM_STEP.powf(-lines)).max(self.near);
+    }
+
+    /// Move by `movement` seconds' worth along the camera's right, up and
+    /// forward. The speed follows `distance`, so a close-up moves slowly.
+    fn fly(&mut self, movement: Vec3) {
+        let forward = self.forward();
+        let right = forward.cross(Vec3::Y).normalize_or_zero();
+        let offset = right * movement.x + Vec3::Y * movement.y + forward * movement.z;
+        self.target += offset * self.distance;
+    }
+}
+
+/// The camera's matrices. Declare it in WGSL as
+///
+/// ```wgsl
+/// struct Camera {
+///     view: mat4x4<f32>,
+///     projection: mat4x4<f32>,
+///     view_projection: mat4x4<f32>,
+///     inverse_view: mat4x4<f32>,
+///     inverse_projection: mat4x4<f32>,
+///     inverse_view_projection: mat4x4<f32>,
+///     // The camera's position in world space, w is 1.
+///     position: vec4<f32>,
+/// };
+///
+/// @group(1) @binding(1)
+/// var<uniform> camera: Camera;
+/// ```
+///
+/// A vertex shader places a vertex with `camera.view_projection`. A
+/// raymarching fragment shader gets its ray from the pixel's position in clip
+/// space, `ndc`:
+///
+/// ```wgsl
+/// let far = camera.inverse_view_projection * vec4<f32>(ndc, 1.0, 1.0);
+/// let direction = normalize(far.xyz / far.w - camera.position.xyz);
+/// ```
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    pub inverse_view: Mat4,
+    pub inverse_projection: Mat4,
+    pub inverse_view_projection: Mat4,
+    pub position: Vec4,
+}
+
+impl CameraUniform {
+    pub fn new(camera: &Camera, resolution: [f32; 2]) -> Self {
+        let view = camera.view();
+        let projection = camera.projection(resolution[0] / resolution[1]);
+        let view_projection = projection * view;
+        Self {
+            view,
+            projection,
+            view_projection,
+            inverse_view: view.inverse(),
+            inverse_projection: projection.inverse(),
+            inverse_view_projection: view_projection.inverse(),
+            position: camera.position().extend(1.0),
+        }
+    }
+}
+
+/// Drives the camera from the mouse and keyboard: dragging with the left
+/// button orbits the target, with the right button turns in place, scrolling
+/// zooms, and WASD flies, with Q and E for down and up.
+#[derive(Default)]
+pub struct CameraControls {
+    cursor: Option<[f32; 2]>,
+    /// The button held down since a drag started outside the UI.
+    dragging: Option<MouseButton>,
+    /// The flying keys held down.
+    held: HashSet<VirtualKeyCode>,
+}
+
+impl CameraControls {
+    /// React to `event`. Presses and scrolling the UI `consumed` don't move
+    /// the camera; releases always count, so nothing is left held down.
+    pub fn handle_window_event(
+        &mut self,
+        camera: &mut Camera,
+        event: &WindowEvent,
+        consumed: bool,
+    ) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = [position.x as f32, position.y as f32];
+                if let (Some(button), Some(last)) = (self.dragging, self.cursor) {
+                    let (dx, dy) = (position[0] - last[0], position[1] - last[1]);
+                    match button {
+                        MouseButton::Left => camera.orbit(dx, dy),
+                        _ => camera.look(dx, dy),
+                    }
+                }
+                self.cursor = Some(position);
+            }
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed if !consumed && self.dragging.is_none() => {
+                    if matches!(button, MouseButton::Left | MouseButton::Right) {
+                        self.dragging = Some(*button);
+                    }
+                }
+                ElementState::Released if self.dragging == Some(*button) => self.dragging = None,
+                _ => {}
+            },
+            WindowEvent::MouseWheel { delta, .. } if !consumed => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                camera.zoom(lines);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => match state {
+                ElementState::Pressed if !consumed && fly_direction(*key).is_some() => {
+                    self.held.insert(*key);
+                }
+                ElementState::Released => {
+                    self.held.remove(key);
+                }
+                _ => {}
+            },
+            WindowEvent::Focused(false) => {
+                self.dragging = None;
+                self.held.clear();
+            }
+            _ => {}
+        }
+    }
+
+    /// Fly for `delta_time` seconds with the keys that are held down.
+    pub fn update(&self, camera: &mut Camera, delta_time: f32) {
+        let movement: Vec3 = self.held.iter().filter_map(|key| fly_direction(*key)).sum();
+        if movement != Vec3::ZERO {
+            camera.fly(movement * delta_time);
+        }
+    }
+}
+
+/// The direction a flying key moves in: right, up and forward.
+fn fly_direction(key: VirtualKeyCode) -> Option<Vec3> {
+    match key {
+        VirtualKeyCode::W => Some(Vec3::Z),
+        VirtualKeyCode::S => Some(Vec3::NEG_Z),
+        VirtualKeyCode::D => Some(Vec3::X),
+        VirtualKeyCode::A => Some(Vec3::NEG_X),
+        VirtualKeyCode::E => Some(Vec3::Y),
+        VirtualKeyCode::Q => Some(Vec3::NEG_Y),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{a} isn't {b}");
+    }
+
+    #[test]
+    fn the_eye_sits_behind_the_target() {
+        let camera = Camera::default();
+        assert_near(camera.position(), Vec3::new(0.0, 0.0, 3.0));
+
+        let turned = Camera {
+            target: Vec3::Y,
+            yaw: FRAC_PI_2,
+            ..camera
+        };
+        assert_near(turned.forward(), Vec3::NEG_X);
+        assert_near(turned.position(), Vec3::new(3.0, 1.0, 0.0));
+
+        // Pitching down raises the eye.
+        let above = Camera {
+            pitch: FRAC_PI_2 / 2.0,
+            ..camera
+        };
+        let height = 3.0 * (FRAC_PI_2 / 2.0).sin();
+        assert_near(above.position(), Vec3::new(0.0, height, height));
+    }
+
+    #[test]
+    fn the_view_puts_the_target_straight_ahead() {
+        let camera = Camera {
+            target: Vec3::new(1.0, 2.0, 3.0),
+            yaw: 0.7,
+            pitch: -0.4,
+            ..Camera::default()
+        };
+        let target = camera.view().transform_point3(camera.target);
+        assert_near(target, Vec3::new(0.0, 0.0, -camera.distance));
+    }
+
+    #[test]
+    fn the_projection_follows_fov_and_aspect() {
+        let camera = Camera {
+            fov: 90.0,
+            ..Camera::default()
+        };
+        let projection = camera.projection(2.0);
+        assert!((projection.y_axis.y - 1.0).abs() < 1e-5);
+        assert!((projection.x_axis.x - 0.5).abs() < 1e-5);
+
+        let depth = |z: f32| projection.project_point3(Vec3::new(0.0, 0.0, -z)).z;
+        assert!(depth(camera.near).abs() < 1e-5);
+        assert!((depth(camera.far) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn the_uniform_inverts_its_matrices() {
+        let camera = Camera {
+            target: Vec3::new(0.5, -1.0, 2.0),
+            yaw: 1.2,
+            pitch: 0.3,
+            ..Camera::default()
+        };
+        let uniform = CameraUniform::new(&camera, [800.0, 600.0]);
+        let identity = uniform.inverse_view_projection * uniform.view_projection;
+        assert!(identity.abs_diff_eq(Mat4::IDENTITY, 1e-3), "{identity}");
+        let identity = uniform.inverse_view * uniform.view;
+        assert!(identity.abs_diff_eq(Mat4::IDENTITY, 1e-3), "{identity}");
+        assert_near(uniform.position.truncate(), camera.position());
+        assert_eq!(uniform.position.w, 1.0);
+    }
+
+    #[test]
+    fn pitch_stops_short_of_straight_up_and_down() {
+        let mut camera = Camera::default();
+        camera.orbit(0.0, 1e6);
+        assert_eq!(camera.pitch, MAX_PITCH);
+        assert!(camera.pitch < FRAC_PI_2);
+        camera.look(0.0, -1e6);
+        assert_eq!(camera.pitch, -MAX_PITCH);
+    }
+
+    #[test]
+    fn looking_around_keeps_the_eye_in_place() {
+        let mut camera = Camera::default();
+        let eye = camera.position();
+        camera.look(120.0, -40.0);
+        assert_near(camera.position(), eye);
+        assert_ne!(camera.target, Vec3::ZERO);
+    }
+
+    #[test]
+    fn zooming_and_flying_scale_with_distance() {
+        let mut camera = Camera::default();
+        camera.zoom(1.0);
+        assert!((camera.distance - 3.0 / ZOOM_STEP).abs() < 1e-5);
+        camera.zoom(1e6);
+        assert_eq!(camera.distance, camera.near);
+
+        let mut camera = Camera::default();
+        camera.fly(Vec3::Z);
+        assert_near(camera.target, Vec3::new(0.0, 0.0, -3.0));
+        camera.fly(Vec3::X);
+        assert_near(camera.target, Vec3::new(3.0, 0.0, -3.0));
+    }
+}
@@ -0,0 +1,432 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use crate::audio::{AudioTrack, AUDIO_TEXTURE_WIDTH};
+use crate::shader::{self, ShaderError};
+
+/// How many textures a shader can sample, like Shadertoy's `iChannel0` to
+/// `iChannel3`.
+pub const CHANNEL_COUNT: u32 = 4;
+
+/// The extensions of images that can go on a channel.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg"];
+
+/// The extensions of audio files that can go on a channel, see `AudioTrack`.
+const AUDIO_EXTENSIONS: &[&str] = &["wav"];
+
+/// How a channel's texture is filtered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+impl Filter {
+    pub const ALL: [Self; 2] = [Self::Nearest, Self::Linear];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Nearest => "nearest",
+            Self::Linear => "linear",
+        }
+    }
+}
+
+/// What a channel's texture does outside of 0 to 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrap {
+    #[default]
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+impl Wrap {
+    pub const ALL: [Self; 3] = [Self::Clamp, Self::Repeat, Self::Mirror];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Clamp => "clamp",
+            Self::Repeat => "repeat",
+            Self::Mirror => "mirror",
+        }
+    }
+
+    fn address_mode(self) -> wgpu::AddressMode {
+        match self {
+            Self::Clamp => wgpu::AddressMode::ClampToEdge,
+            Self::Repeat => wgpu::AddressMode::Repeat,
+            Self::Mirror => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+/// How a channel is sampled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sampling {
+    pub filter: Filter,
+    pub wrap: Wrap,
+}
+
+/// An image or audio file on one of the shader's channels, as picked in the
+/// UI, and saved with the session.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelInput {
+    pub file: Option<PathBuf>,
+    pub sampling: Sampling,
+    /// Upload an image bottom row first, for Shadertoy shaders, whose
+    /// texture coordinates start at the bottom.
+    pub flip: bool,
+    /// Decode an image from sRGB when it's sampled. Off for Shadertoy
+    /// shaders, which expect the stored values, and data like normal maps.
+    pub srgb: bool,
+}
+
+impl Default for ChannelInput {
+    fn default() -> Self {
+        Self {
+            file: None,
+            sampling: Sampling::default(),
+            flip: false,
+            srgb: true,
+        }
+    }
+}
+
+/// The images and audio files in `dir` that can go on a channel, sorted by
+/// path.
+pub fn available_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_image(path) || is_audio(path))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn is_image(path: &Path) -> bool {
+    has_extension(path, IMAGE_EXTENSIONS)
+}
+
+fn is_audio(path: &Path) -> bool {
+    has_extension(path, AUDIO_EXTENSIONS)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// The channel textures and the bind group that exposes them at `@group(2)`.
+/// Channel `i` has its texture at binding `2 * i` and its sampler at
+/// `2 * i + 1`:
+///
+/// ```wgsl
+/// @group(2) @binding(0)
+/// var channel0: texture_2d<f32>;
+/// @group(2) @binding(1)
+/// var channel0_sampler: sampler;
+/// ```
+///
+/// A channel can have an image or audio on it, a pass graph's buffer or storage texture,
+/// or nothing, which is a single black pixel.
+///
+/// Every scene and compute pipeline's layout includes `layout`, whether or not
+/// the shader samples anything.
+pub struct ChannelsBinding {
+    pub layout: Arc<wgpu::BindGroupLayout>,
+    empty: wgpu::TextureView,
+    samplers: HashMap<Sampling, wgpu::Sampler>,
+}
+
+impl ChannelsBinding {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let entries: Vec<_> = (0..CHANNEL_COUNT)
+            .flat_map(|channel| {
+                [
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2 * channel,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT
+                            | wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2 * channel + 1,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT
+                            | wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ]
+            })
+            .collect();
+        let layout = Arc::new(
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Channels Bind Group Layout"),
+                entries: &entries,
+            }),
+        );
+
+        let black = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Empty Channel"),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            &[0, 0, 0, 255],
+        );
+        let empty = black.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut samplers = HashMap::new();
+        for filter in Filter::ALL {
+            for wrap in Wrap::ALL {
+                let filter_mode = match filter {
+                    Filter::Nearest => wgpu::FilterMode::Nearest,
+                    Filter::Linear => wgpu::FilterMode::Linear,
+                };
+                let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                    label: Some("Channel Sampler"),
+                    address_mode_u: wrap.address_mode(),
+                    address_mode_v: wrap.address_mode(),
+                    mag_filter: filter_mode,
+                    min_filter: filter_mode,
+                    ..Default::default()
+                });
+                samplers.insert(Sampling { filter, wrap }, sampler);
+            }
+        }
+
+        Self {
+            layout,
+            empty,
+            samplers,
+        }
+    }
+
+    /// A bind group with `channels` on the first channels and the rest, and
+    /// any that are `None`, empty.
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        channels: &[Option<(&wgpu::TextureView, Sampling)>],
+    ) -> wgpu::BindGroup {
+        let entries: Vec<_> = (0..CHANNEL_COUNT)
+            .flat_map(|channel| {
+                let (view, sampling) = channels
+                    .get(channel as usize)
+                    .copied()
+                    .flatten()
+                    .unwrap_or((&self.empty, Sampling::default()));
+                [
+                    wgpu::BindGroupEntry {
+                        binding: 2 * channel,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2 * channel + 1,
+                        resource: wgpu::BindingResource::Sampler(&self.samplers[&sampling]),
+                    },
+                ]
+            })
+            .collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Channels Bind Group"),
+            layout: &self.layout,
+            entries: &entries,
+        })
+    }
+}
+
+/// The textures for the files picked for the channels. Images are uploaded
+/// once; audio is analysed again every frame.
+#[derive(Default)]
+pub struct ChannelTextures {
+    loaded: [Option<LoadedFile>; CHANNEL_COUNT as usize],
+}
+
+/// What a channel's texture was last loaded from, and the texture if that
+/// worked.
+struct LoadedFile {
+    path: PathBuf,
+    flip: bool,
+    srgb: bool,
+    texture: Option<(wgpu::Texture, wgpu::TextureView)>,
+    audio: Option<AudioTrack>,
+}
+
+impl ChannelTextures {
+    /// Load the files in `inputs` that changed since the last call, and bring
+    /// audio textures up to `time`. Returns the first failure; a channel whose
+    /// file doesn't load stays empty until it's picked again or the file
+    /// changes.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        inputs: &[ChannelInput],
+        time: f32,
+    ) -> Result<(), ShaderError> {
+        let mut result = Ok(());
+        for (loaded, input) in self.loaded.iter_mut().zip(inputs) {
+            let Some(path) = &input.file else {
+                *loaded = None;
+                continue;
+            };
+            let current = loaded.as_ref().is_some_and(|loaded| {
+                loaded.path == *path && loaded.flip == input.flip && loaded.srgb == input.srgb
+            });
+            if !current {
+                let (texture, audio) = match load_file(device, queue, path, input) {
+                    Ok((texture, audio)) => (Some(texture), audio),
+                    Err(err) => {
+                        result = result.and(Err(err));
+                        (None, None)
+                    }
+                };
+                *loaded = Some(LoadedFile {
+                    path: path.clone(),
+                    flip: input.flip,
+                    srgb: input.srgb,
+                    texture,
+                    audio,
+                });
+            }
+
+            if let Some(LoadedFile {
+                texture: Some((texture, _)),
+                audio: Some(audio),
+                ..
+            }) = loaded
+            {
+                queue.write_texture(
+                    texture.as_image_copy(),
+                    &audio.analyse(time),
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(AUDIO_TEXTURE_WIDTH),
+                        rows_per_image: None,
+                    },
+                    texture.size(),
+                );
+            }
+        }
+        result
+    }
+
+    /// Forget the files that are among `changed`, so the next `update` loads
+    /// them again.
+    pub fn reload(&mut self, changed: &[PathBuf]) {
+        for loaded in &mut self.loaded {
+            let stale = loaded.as_ref().is_some_and(|loaded| {
+                changed
+                    .iter()
+                    .any(|path| shader::same_file(path, &loaded.path))
+            });
+            if stale {
+                *loaded = None;
+            }
+        }
+    }
+
+    /// The texture on `channel`, if it has a file that loaded.
+    pub fn view(&self, channel: usize) -> Option<&wgpu::TextureView> {
+        let (_, view) = self.loaded.get(channel)?.as_ref()?.texture.as_ref()?;
+        Some(view)
+    }
+}
+
+/// A channel's texture for `path`, and the track that fills it in if it's
+/// audio.
+fn load_file(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: &Path,
+    input: &ChannelInput,
+) -> Result<((wgpu::Texture, wgpu::TextureView), Option<AudioTrack>), ShaderError> {
+    if is_audio(path) {
+        let audio = AudioTrack::load(path)?;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&path.to_string_lossy()),
+            size: wgpu::Extent3d {
+                width: AUDIO_TEXTURE_WIDTH,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        return Ok(((texture, view), Some(audio)));
+    }
+
+    let image = image::open(path)
+        .map_err(|err| ShaderError::new(path, format!("can't load image: {err}")))?;
+    let image = if input.flip { image.flipv() } else { image };
+    let image = image.to_rgba8();
+    let max = device.limits().max_texture_dimension_2d;
+    if image.width() > max || image.height() > max {
+        let message = format!("the image is bigger than the {max}x{max} textures can be");
+        return Err(ShaderError::new(path, message));
+    }
+
+    let format = if input.srgb {
+        wgpu::TextureFormat::Rgba8UnormSrgb
+    } else {
+        wgpu::TextureFormat::Rgba8Unorm
+    };
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some(&path.to_string_lossy()),
+            size: wgpu::Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        &image,
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    Ok(((texture, view), None))
+}
@@ -0,0 +1,211 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+use winit::dpi::PhysicalSize;
+
+use crate::watcher::WatchConfig;
+
+#[derive(Parser)]
+#[command(version, about = "A playground for WGSL, GLSL and SPIR-V shaders")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Open the interactive playground, reloading the shader when it changes.
+    Run(RunArgs),
+    /// Render a single frame to an image without opening a window.
+    Render(RenderArgs),
+    /// Render frames at a fixed frame rate to numbered PNGs in a directory.
+    Animate(AnimateArgs),
+    /// Render a sound shader's samples to a WAV file, without an audio device.
+    Sound(SoundArgs),
+    /// Check every shader under a directory and fail if any has errors.
+    Validate {
+        /// A directory to search for shaders, or a single shader file.
+        dir: PathBuf,
+    },
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// The shader to play with. Post-processing shaders are looked up in a
+    /// `post` directory next to it.
+    pub shader: PathBuf,
+    /// Directories to watch for changes. Defaults to the shader's directory.
+    #[arg(long = "watch", value_name = "DIR")]
+    pub watch_dirs: Vec<PathBuf>,
+    /// How long a file has to be quiet before it's reloaded, in milliseconds.
+    #[arg(long, default_value_t = 250)]
+    pub debounce_ms: u64,
+    /// Poll for changes instead of using file system events, for network
+    /// mounts and containers where events don't arrive.
+    #[arg(long)]
+    pub poll: bool,
+    /// How often to poll, in milliseconds.
+    #[arg(long, default_value_t = 500)]
+    pub poll_interval_ms: u64,
+    /// An `.obj`, `.gltf` or `.glb` mesh for shaders with a vertex stage to
+    /// draw, instead of a triangle.
+    #[arg(long)]
+    pub mesh: Option<PathBuf>,
+}
+
+impl RunArgs {
+    pub fn watch_config(&self) -> WatchConfig {
+        let dirs = if self.watch_dirs.is_empty() {
+            vec![shader_dir(&self.shader)]
+        } else {
+            self.watch_dirs.clone()
+        };
+        WatchConfig {
+            dirs,
+            debounce: Duration::from_millis(self.debounce_ms),
+            poll: self.poll,
+            poll_interval: Duration::from_millis(self.poll_interval_ms),
+        }
+    }
+}
+
+/// The directory a shader lives in.
+fn shader_dir(shader: &Path) -> PathBuf {
+    match shader.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Which session a headless render starts from, see `Session`.
+#[derive(Args)]
+pub struct SessionArgs {
+    /// Start from a saved session: `name.session.toml` next to the shader, or
+    /// FILE with `--session=FILE`. Without it, renders start from the
+    /// defaults whether or not a session was saved.
+    #[arg(long, value_name = "FILE", require_equals = true)]
+    pub session: Option<Option<PathBuf>>,
+}
+
+#[derive(Args)]
+pub struct RenderArgs {
+    pub shader: PathBuf,
+    /// Image size in pixels.
+    #[arg(long, default_value = "800x600", value_parser = parse_size)]
+    pub size: PhysicalSize<u32>,
+    /// The time in seconds the frame is rendered at.
+    #[arg(long, default_value_t = 0.0)]
+    pub time: f32,
+    /// Where to write the PNG.
+    #[arg(short, long)]
+    pub output: PathBuf,
+    /// Turn on an `#ifdef` switch, on top of the session's. Can be repeated.
+    #[arg(short = 'D', long = "define", value_name = "NAME")]
+    pub defines: Vec<String>,
+    /// Put an image or WAV file on the next channel, starting at channel 0,
+    /// instead of the session's. Audio plays along with the frame clock. Can
+    /// be repeated.
+    #[arg(long = "channel", value_name = "FILE")]
+    pub channels: Vec<PathBuf>,
+    #[command(flatten)]
+    pub session: SessionArgs,
+    /// An `.obj`, `.gltf` or `.glb` mesh for shaders with a vertex stage to
+    /// draw, instead of a triangle.
+    #[arg(long)]
+    pub mesh: Option<PathBuf>,
+    /// Use a software adapter even if there's a GPU.
+    #[arg(long)]
+    pub fallback: bool,
+}
+
+#[derive(Args)]
+pub struct AnimateArgs {
+    pub shader: PathBuf,
+    /// Image size in pixels.
+    #[arg(long, default_value = "800x600", value_parser = parse_size)]
+    pub size: PhysicalSize<u32>,
+    /// How many frames to render.
+    #[arg(long)]
+    pub frames: u32,
+    /// Frames per second of simulated time.
+    #[arg(long, default_value_t = 60.0)]
+    pub fps: f32,
+    /// The time in seconds of the first frame.
+    #[arg(long, default_value_t = 0.0)]
+    pub start: f32,
+    /// The directory to write `frame_00000.png`, `frame_00001.png`, ... to.
+    /// Created if it doesn't exist.
+    #[arg(short, long)]
+    pub output: PathBuf,
+    /// Turn on an `#ifdef` switch, on top of the session's. Can be repeated.
+    #[arg(short = 'D', long = "define", value_name = "NAME")]
+    pub defines: Vec<String>,
+    /// Put an image or WAV file on the next channel, starting at channel 0,
+    /// instead of the session's. Audio plays along with the frame clock. Can
+    /// be repeated.
+    #[arg(long = "channel", value_name = "FILE")]
+    pub channels: Vec<PathBuf>,
+    #[command(flatten)]
+    pub session: SessionArgs,
+    /// An `.obj`, `.gltf` or `.glb` mesh for shaders with a vertex stage to
+    /// draw, instead of a triangle.
+    #[arg(long)]
+    pub mesh: Option<PathBuf>,
+    /// Use a software adapter even if there's a GPU.
+    #[arg(long)]
+    pub fallback: bool,
+}
+
+#[derive(Args)]
+pub struct SoundArgs {
+    /// A compute shader that writes stereo samples into a storage buffer.
+    pub shader: PathBuf,
+    /// How long the sound is, in seconds.
+    #[arg(long, default_value_t = 10.0)]
+    pub length: f32,
+    /// Samples per second.
+    #[arg(long, default_value_t = 44100)]
+    pub sample_rate: u32,
+    /// Where to write the WAV file.
+    #[arg(short, long)]
+    pub output: PathBuf,
+    /// Turn on an `#ifdef` switch. Can be repeated.
+    #[arg(short = 'D', long = "define", value_name = "NAME")]
+    pub defines: Vec<String>,
+    /// Use a software adapter even if there's a GPU.
+    #[arg(long)]
+    pub fallback: bool,
+}
+
+/// Parse a size like `640x480`.
+fn parse_size(size: &str) -> Result<PhysicalSize<u32>, String> {
+    let parse = |value: &str| match value.trim().parse() {
+        Ok(0) | Err(_) => Err(format!("expected a size like 640x480, got {size:?}")),
+        Ok(value) => Ok(value),
+    };
+    let (width, height) = size
+        .split_once('x')
+        .ok_or_else(|| format!("expected a size like 640x480, got {size:?}"))?;
+    Ok(PhysicalSize::new(parse(width)?, parse(height)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_parse_as_width_by_height() {
+        assert_eq!(parse_size("640x480"), Ok(PhysicalSize::new(640, 480)));
+        assert_eq!(parse_size(" 1 x 2 "), Ok(PhysicalSize::new(1, 2)));
+    }
+
+    #[test]
+    fn malformed_or_empty_sizes_are_rejected() {
+        for size in [
+            "640", "640x", "x480", "0x480", "640x0", "-1x480", "axb", "640*480",
+        ] {
+            assert!(parse_size(size).is_err(), "{size} parsed");
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+use crate::shader::{self, ShaderError};
+
+/// Runs shader builds on worker threads so a big shader doesn't stall the
+/// frame, and hands the results back once they're done. Builds are keyed;
+/// starting a new build for a key that's still building supersedes the old
+/// one, whose result is dropped.
+pub struct Compiler<K, T> {
+    /// A build's result, or `None` if it panicked.
+    sender: Sender<(K, u64, Option<T>)>,
+    receiver: Receiver<(K, u64, Option<T>)>,
+    /// The newest build of every key that's still building.
+    pending: HashMap<K, u64>,
+    next_build: u64,
+}
+
+impl<K, T> Compiler<K, T>
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    T: Send + 'static,
+{
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender,
+            receiver,
+            pending: HashMap::new(),
+            next_build: 0,
+        }
+    }
+
+    pub fn spawn(&mut self, key: K, build: impl FnOnce() -> T + Send + 'static) {
+        let id = self.next_build;
+        self.next_build += 1;
+        self.pending.insert(key.clone(), id);
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            // A build that panics still reports back, so it doesn't stay
+            // pending forever and `finished(true)` doesn't wait for it.
+            let value = panic::catch_unwind(AssertUnwindSafe(build)).ok();
+            // Nobody's listening if the renderer is gone, which is fine.
+            _ = sender.send((key, id, value));
+        });
+    }
+
+    pub fn is_pending(&self, key: &K) -> bool {
+        self.pending.contains_key(key)
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Forget every build in progress. Their results are dropped.
+    pub fn cancel_all(&mut self) {
+        self.pending.clear();
+    }
+
+    /// The builds that finished since the last call. With `wait`, blocks until
+    /// nothing is building anymore. Builds that panicked are only dropped;
+    /// wrap the build in `shader::catch_panic` to hear about them.
+    pub fn finished(&mut self, wait: bool) -> Vec<(K, T)> {
+        let mut finished = vec![];
+        loop {
+            let next = if wait && self.is_busy() {
+                self.receiver.recv().ok()
+            } else {
+                self.receiver.try_recv().ok()
+            };
+            let Some((key, id, value)) = next else {
+                return finished;
+            };
+            if self.pending.get(&key) == Some(&id) {
+                self.pending.remove(&key);
+                if let Some(value) = value {
+                    finished.push((key, value));
+                }
+            }
+        }
+    }
+}
+
+/// A finished build: everything it read and the pipeline.
+type Build<P> = (Vec<PathBuf>, Result<P, ShaderError>);
+
+/// Pipelines built in the background, one per key, that are rebuilt when a
+/// file they read changes. A key that fails keeps its last good pipeline; one
+/// that never built is skipped until the next reload, so a broken file
+/// doesn't recompile every frame.
+pub struct Builds<K, P> {
+    pipelines: HashMap<K, P>,
+    /// Everything each key's last build read, whether or not it succeeded.
+    files: HashMap<K, Vec<PathBuf>>,
+    failed: HashSet<K>,
+    compiler: Compiler<K, Build<P>>,
+}
+
+impl<K, P> Builds<K, P>
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    P: Send + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            pipelines: HashMap::new(),
+            files: HashMap::new(),
+            failed: HashSet::new(),
+            compiler: Compiler::new(),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&P> {
+        self.pipelines.get(key)
+    }
+
+    /// Whether `key` has never been built or tried.
+    pub fn is_new(&self, key: &K) -> bool {
+        !self.pipelines.contains_key(key)
+            && !self.failed.contains(key)
+            && !self.compiler.is_pending(key)
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.compiler.is_busy()
+    }
+
+    /// Start building `key`. The build adds every file it reads to the list
+    /// it's given.
+    pub fn spawn(
+        &mut self,
+        key: K,
+        build: impl FnOnce(&mut Vec<PathBuf>) -> Result<P, ShaderError> + Send + 'static,
+    ) {
+        self.compiler.spawn(key, move || {
+            let mut files = vec![];
+            let pipeline = build(&mut files);
+            (files, pipeline)
+        });
+    }
+
+    /// Swap in the builds that have finished. With `wait`, blocks until every
+    /// build is done. Returns the first failure.
+    pub fn finish(&mut self, wait: bool) -> Result<(), ShaderError> {
+        let mut result = Ok(());
+        for (key, (files, pipeline)) in self.compiler.finished(wait) {
+            self.files.insert(key.clone(), files);
+            match pipeline {
+                Ok(pipeline) => {
+                    self.failed.remove(&key);
+                    self.pipelines.insert(key, pipeline);
+                }
+                Err(err) => {
+                    if !self.pipelines.contains_key(&key) {
+                        self.failed.insert(key);
+                    }
+                    result = result.and(Err(err));
+                }
+            }
+        }
+        result
+    }
+
+    /// The keys that read any of `changed`, to be built again. They're no
+    /// longer considered failed.
+    pub fn outdated(&mut self, changed: &[PathBuf]) -> Vec<K> {
+        let keys: Vec<_> = self
+            .pipelines
+            .keys()
+            .chain(&self.failed)
+            .filter(|key| shader::depends_on(&self.files[*key], changed))
+            .cloned()
+            .collect();
+        for key in &keys {
+            self.failed.remove(key);
+        }
+        keys
+    }
+}
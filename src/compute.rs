@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+use crate::overrides::Constants;
+use crate::preprocess::Defines;
+use crate::shader::{self, ShaderError};
+use crate::storage::DeclaredBuffers;
+use crate::uniforms::UniformBlock;
+
+/// A compute pass's pipeline. It has the same layout as a scene pipeline: its
+/// own uniform at `@group(0)`, then the globals, the channels and the storage.
+pub struct ComputePipeline {
+    pub pipeline: wgpu::ComputePipeline,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    // Layout and default values of the shader's uniform.
+    pub uniforms: UniformBlock,
+    /// The entry point's `@workgroup_size`.
+    pub workgroup_size: [u32; 3],
+}
+
+impl ComputePipeline {
+    /// How many workgroups it takes to run at least `threads` invocations.
+    pub fn workgroups(&self, threads: [u32; 3]) -> [u32; 3] {
+        let mut workgroups = [0; 3];
+        for axis in 0..3 {
+            workgroups[axis] = threads[axis].div_ceil(self.workgroup_size[axis].max(1));
+        }
+        workgroups
+    }
+}
+
+/// Build the pipeline for `entry_point` in `shader_path`, or its first compute
+/// entry point.
+pub fn create_pipeline(
+    device: &wgpu::Device,
+    // Group 1 on, the same for every compute pipeline.
+    shared_layouts: &[&wgpu::BindGroupLayout],
+    storage: &DeclaredBuffers,
+    shader_path: &Path,
+    entry_point: Option<&str>,
+    shader_files: &mut Vec<PathBuf>,
+) -> Result<ComputePipeline, ShaderError> {
+    let loaded = shader::load_shader(
+        shader_path,
+        &Defines::new(),
+        &Constants::new(),
+        shader_files,
+    )?;
+    let uniforms = UniformBlock::reflect(&loaded.module, &loaded.source);
+    let (stage, workgroup_size) = loaded.compute_stage(shader_path, entry_point)?;
+    storage.check(shader_path, &[&stage], shader_files)?;
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Compute Uniform Bind Group Layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(uniforms.buffer_size() as u64),
+            },
+            count: None,
+        }],
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Compute Pipeline Layout"),
+        bind_group_layouts: &[&[&bind_group_layout], shared_layouts].concat(),
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = shader::capture_errors(device, shader_path, || {
+        let module = stage.create_module(device, "Compute Shader");
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline"),
+            layout: Some(&layout),
+            module: &module,
+            entry_point: &stage.entry_point,
+        })
+    })?;
+
+    Ok(ComputePipeline {
+        pipeline,
+        bind_group_layout,
+        uniforms,
+        workgroup_size,
+    })
+}
@@ -0,0 +1,275 @@
+use std::{sync::Arc, time::Instant};
+
+use chrono::{Datelike, Local, Timelike};
+use winit::event::{ElementState, MouseButton, WindowEvent};
+
+use crate::camera::CameraUniform;
+
+/// Per-frame values every shader can read. Declare it in WGSL as
+///
+/// ```wgsl
+/// struct Globals {
+///     // xy: cursor position, zw: where the last click started. In pixels,
+///     // origin at the top left like `@builtin(position)`.
+///     mouse: vec4<f32>,
+///     // year, month (1-12), day (1-31), seconds since midnight.
+///     date: vec4<f32>,
+///     resolution: vec2<f32>,
+///     time: f32,
+///     delta_time: f32,
+///     frame: u32,
+///     // Bit 0: left, bit 1: right, bit 2: middle.
+///     mouse_buttons: u32,
+/// };
+///
+/// @group(1) @binding(0)
+/// var<uniform> globals: Globals;
+/// ```
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Globals {
+    pub mouse: [f32; 4],
+    pub date: [f32; 4],
+    pub resolution: [f32; 2],
+    pub time: f32,
+    pub delta_time: f32,
+    pub frame: u32,
+    pub mouse_buttons: u32,
+    _padding: [u32; 2],
+}
+
+impl Globals {
+    pub fn new(clock: &Clock, mouse: &Mouse, resolution: [f32; 2]) -> Self {
+        Self {
+            mouse: [
+                mouse.position[0],
+                mouse.position[1],
+                mouse.click[0],
+                mouse.click[1],
+            ],
+            date: clock.date(),
+            resolution,
+            time: clock.time,
+            delta_time: clock.delta_time,
+            frame: clock.frame,
+            mouse_buttons: mouse.buttons,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// Frame timing. Time starts at zero on the first frame and follows the wall
+/// clock, unless the clock is fixed, in which case every frame is exactly one
+/// step apart.
+pub struct Clock {
+    start: Instant,
+    last: Option<Instant>,
+    step: Option<f32>,
+    /// `time` of the first frame of a fixed clock.
+    origin: f32,
+    pub time: f32,
+    pub delta_time: f32,
+    pub frame: u32,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last: None,
+            step: None,
+            origin: 0.0,
+            time: 0.0,
+            delta_time: 0.0,
+            frame: 0,
+        }
+    }
+
+    /// A clock whose first frame is at `start` seconds and that advances by
+    /// `step` seconds per frame, however long frames actually take.
+    pub fn fixed(start: f32, step: f32) -> Self {
+        Self {
+            step: Some(step),
+            origin: start,
+            time: start,
+            delta_time: step,
+            ..Self::new()
+        }
+    }
+
+    /// Advance to the next frame.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        match (self.last, self.step) {
+            (Some(_), Some(step)) => {
+                // Multiply rather than accumulate so long renders don't drift.
+                self.frame += 1;
+                self.time = self.origin + self.frame as f32 * step;
+            }
+            (Some(last), None) => {
+                self.frame += 1;
+                self.delta_time = (now - last).as_secs_f32();
+                self.time = (now - self.start).as_secs_f32();
+            }
+            (None, Some(_)) => {}
+            (None, None) => self.start = now,
+        }
+        self.last = Some(now);
+    }
+
+    /// Year, month, day and seconds since midnight. A fixed clock doesn't look
+    /// at the wall clock: its date is 2000-01-01 and the seconds are `time`,
+    /// so renders don't depend on when they were made.
+    pub fn date(&self) -> [f32; 4] {
+        if self.step.is_some() {
+            return [2000.0, 1.0, 1.0, self.time];
+        }
+        let now = Local::now();
+        let seconds = now.num_seconds_from_midnight() as f32 + now.nanosecond() as f32 * 1e-9;
+        [
+            now.year() as f32,
+            now.month() as f32,
+            now.day() as f32,
+            seconds,
+        ]
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mouse {
+    pub position: [f32; 2],
+    pub click: [f32; 2],
+    pub buttons: u32,
+}
+
+impl Mouse {
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.position = [position.x as f32, position.y as f32];
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let bit = match button {
+                    MouseButton::Left => 1,
+                    MouseButton::Right => 2,
+                    MouseButton::Middle => 4,
+                    MouseButton::Other(_) => return,
+                };
+                match state {
+                    ElementState::Pressed => {
+                        self.buttons |= bit;
+                        self.click = self.position;
+                    }
+                    ElementState::Released => self.buttons &= !bit,
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The globals and camera uniform buffers and the bind group that exposes
+/// them at `@group(1)`, the globals at binding 0 and the camera at binding 1.
+/// Every pipeline's layout includes `layout`, which is shared with pipelines
+/// built in the background.
+pub struct GlobalsBinding {
+    pub layout: Arc<wgpu::BindGroupLayout>,
+    pub bind_group: wgpu::BindGroup,
+    buffer: wgpu::Buffer,
+    camera_buffer: wgpu::Buffer,
+}
+
+impl GlobalsBinding {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let size = std::mem::size_of::<Globals>() as u64;
+        let camera_size = std::mem::size_of::<CameraUniform>() as u64;
+        let entry = |binding, size| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(size),
+            },
+            count: None,
+        };
+        let layout = Arc::new(
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Globals Bind Group Layout"),
+                entries: &[entry(0, size), entry(1, camera_size)],
+            }),
+        );
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Globals Buffer"),
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Camera Buffer"),
+            size: camera_size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Globals Bind Group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        Self {
+            layout,
+            bind_group,
+            buffer,
+            camera_buffer,
+        }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, globals: &Globals, camera: &CameraUniform) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(globals));
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(camera));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_clock_steps_exactly() {
+        let mut clock = Clock::fixed(2.0, 0.5);
+        clock.tick();
+        assert_eq!((clock.frame, clock.time, clock.delta_time), (0, 2.0, 0.5));
+        clock.tick();
+        assert_eq!((clock.frame, clock.time, clock.delta_time), (1, 2.5, 0.5));
+        clock.tick();
+        assert_eq!((clock.frame, clock.time), (2, 3.0));
+    }
+
+    #[test]
+    fn fixed_clock_does_not_drift() {
+        let step = 1.0 / 60.0;
+        let mut clock = Clock::fixed(0.0, step);
+        for _ in 0..=6000 {
+            clock.tick();
+        }
+        assert_eq!(clock.frame, 6000);
+        assert_eq!(clock.time, 6000.0 * step);
+    }
+
+    #[test]
+    fn fixed_clock_date_ignores_the_wall_clock() {
+        let mut clock = Clock::fixed(10.0, 1.0);
+        clock.tick();
+        clock.tick();
+        assert_eq!(clock.date(), [2000.0, 1.0, 1.0, 11.0]);
+    }
+}
@@ -1,20 +1,37 @@
 mod app;
+mod audio;
+mod camera;
+mod channels;
+mod cli;
 pub mod color;
+mod compiler;
+mod compute;
+mod globals;
+mod mesh;
+mod overrides;
+mod passes;
+mod post;
+mod preprocess;
 mod renderer;
+mod session;
+mod shader;
+mod shadertoy;
+mod sound;
+mod storage;
+mod uniforms;
+mod watcher;
 
-use std::path::Path;
-use std::sync::mpsc::{channel, Sender, TryRecvError};
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, TryRecvError};
+use std::time::Instant;
 use std::{
     borrow::BorrowMut,
     collections::HashMap,
     sync::{Arc, Mutex},
 };
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 use dashmap::DashMap;
-use notify::RecursiveMode;
-use notify_debouncer_mini::{new_debouncer, DebouncedEvent, Debouncer};
 use renderer::Renderer;
 use winit::{
     event::{Event, WindowEvent},
@@ -23,29 +40,198 @@ use winit::{
 };
 
 use app::App;
+use clap::Parser;
+use cli::{AnimateArgs, Cli, Command, RenderArgs, RunArgs, SessionArgs, SoundArgs};
+use globals::Clock;
+use overrides::Constants;
+use preprocess::Defines;
+use sound::SoundShader;
+use watcher::ShaderWatcher;
 
-// Add this function to main.rs
-fn watch_shader_files(
-    sender: Sender<std::result::Result<Vec<DebouncedEvent>, Vec<notify::Error>>>,
-) -> Result<Debouncer<notify::ReadDirectoryChangesWatcher>> {
-    let mut debouncer = new_debouncer(Duration::from_millis(250), None, sender)?;
+#[pollster::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
 
-    debouncer
-        .watcher()
-        .watch(Path::new("src/shaders"), RecursiveMode::Recursive)?;
+    match Cli::parse().command {
+        Command::Run(args) => run(args).await,
+        Command::Render(args) => render(args).await,
+        Command::Animate(args) => animate(args).await,
+        Command::Sound(args) => sound(args).await,
+        Command::Validate { dir } => validate(&dir),
+    }
+}
 
-    Ok(debouncer)
+async fn render(args: RenderArgs) -> Result<()> {
+    let mut app = App::new(&args.shader);
+    load_session(&mut app, &args.session)?;
+    app.defines.extend(args.defines.iter().cloned());
+    set_channel_files(&mut app, &args.channels)?;
+    let mut renderer = Renderer::new_headless(
+        args.shader,
+        args.mesh.as_deref(),
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+        args.fallback,
+    )
+    .await?;
+    renderer.set_clock(Clock::fixed(args.time, 0.0));
+    renderer.render_to_png(&mut app, args.size, &args.output)
 }
 
-#[pollster::main]
-async fn main() -> Result<()> {
-    color_eyre::install()?;
+async fn animate(args: AnimateArgs) -> Result<()> {
+    if !args.fps.is_finite() || args.fps <= 0.0 {
+        return Err(eyre!("fps must be positive, got {}", args.fps));
+    }
+    std::fs::create_dir_all(&args.output)?;
+
+    let mut app = App::new(&args.shader);
+    load_session(&mut app, &args.session)?;
+    app.defines.extend(args.defines.iter().cloned());
+    set_channel_files(&mut app, &args.channels)?;
+    let mut renderer = Renderer::new_headless(
+        args.shader,
+        args.mesh.as_deref(),
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+        args.fallback,
+    )
+    .await?;
+    renderer.set_clock(Clock::fixed(args.start, 1.0 / args.fps));
+
+    let started = Instant::now();
+    for frame in 0..args.frames {
+        let path = args.output.join(format!("frame_{frame:05}.png"));
+        renderer.render_to_png(&mut app, args.size, &path)?;
+
+        let done = frame + 1;
+        let elapsed = started.elapsed().as_secs_f32();
+        let eta = elapsed / done as f32 * (args.frames - done) as f32;
+        println!(
+            "frame {done}/{} ({:.0}%), {elapsed:.1}s elapsed, ETA {eta:.1}s",
+            args.frames,
+            100.0 * done as f32 / args.frames as f32,
+        );
+    }
+    Ok(())
+}
+
+async fn sound(args: SoundArgs) -> Result<()> {
+    if !args.length.is_finite() || args.length <= 0.0 {
+        return Err(eyre!("length must be positive, got {}", args.length));
+    }
+    if args.sample_rate == 0 {
+        return Err(eyre!("sample rate must be positive"));
+    }
+
+    let (device, queue) = renderer::request_headless_device(args.fallback).await?;
+    let defines = args.defines.iter().cloned().collect();
+    let shader = SoundShader::new(&device, &args.shader, &defines)?;
+    let started = Instant::now();
+    let samples = shader.render(&device, &queue, args.sample_rate, args.length)?;
+    sound::write_wav(&args.output, args.sample_rate, &samples)?;
+    println!(
+        "{} samples in {:.1}s",
+        samples.len(),
+        started.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
+
+/// Start a headless render from the session `args` ask for, if any.
+fn load_session(app: &mut App, args: &SessionArgs) -> Result<()> {
+    let Some(path) = &args.session else {
+        return Ok(());
+    };
+    if let Some(path) = path {
+        app.session_path = path.clone();
+    }
+    if !app.session_path.is_file() {
+        return Err(eyre!(
+            "there's no session file at {}",
+            app.session_path.display()
+        ));
+    }
+    app.load_session()?;
+    Ok(())
+}
+
+/// Put `files` on the first channels.
+fn set_channel_files(app: &mut App, files: &[PathBuf]) -> Result<()> {
+    if files.len() > app.channels.len() {
+        return Err(eyre!("there are only {} channels", app.channels.len()));
+    }
+    for (input, file) in app.channels.iter_mut().zip(files) {
+        input.file = Some(file.clone());
+    }
+    Ok(())
+}
+
+fn validate(dir: &Path) -> Result<()> {
+    let mut shaders = vec![];
+    find_shaders(dir, &mut shaders)?;
+    shaders.sort();
+
+    // Files other shaders include, like GLSL helpers without a `main`, are
+    // only checked as part of those. The stages of a GLSL shader each get
+    // checked with the other one.
+    let mut results = vec![];
+    let mut included = vec![];
+    for path in &shaders {
+        let mut files = vec![];
+        let result = shader::load_shader(path, &Defines::new(), &Constants::new(), &mut files);
+        let stem = path.with_extension("");
+        included.extend(
+            files
+                .into_iter()
+                .filter(|file| file.with_extension("") != stem),
+        );
+        results.push((path, result));
+    }
+    results.retain(|(path, _)| !included.iter().any(|file| shader::same_file(file, path)));
+
+    let mut failed = 0;
+    for (_, result) in &results {
+        if let Err(err) = result {
+            eprintln!("{err}");
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(eyre!("{failed} of {} shaders have errors", results.len()));
+    }
+    println!("{} shaders ok", results.len());
+    Ok(())
+}
+
+/// Collect the shader files under `path`, or `path` itself if it's a file.
+fn find_shaders(path: &Path, shaders: &mut Vec<PathBuf>) -> Result<()> {
+    if path.is_file() {
+        shaders.push(path.to_owned());
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_shaders(&path, shaders)?;
+        } else if shader::is_shader(&path) {
+            shaders.push(path);
+        }
+    }
+    Ok(())
+}
+
+async fn run(args: RunArgs) -> Result<()> {
+    let watch_config = args.watch_config();
+    let mesh = args.mesh;
+    let shader = args.shader;
+    let title = match shader.file_name() {
+        Some(name) => format!("Shader Playground - {}", name.to_string_lossy()),
+        None => "Shader Playground".to_owned(),
+    };
 
     let event_loop = EventLoop::new();
     let egui_state = egui_winit::State::new(&event_loop);
     let mut egui_state = Arc::new(Mutex::new(egui_state));
     let window = WindowBuilder::new()
-        .with_title("Shader Playground")
+        .with_title(title)
         .build(&event_loop)
         .unwrap();
 
@@ -59,12 +245,24 @@ async fn main() -> Result<()> {
         .collect();
     let contexts = Arc::new(contexts);
 
-    let mut app = App::new();
+    let mut app = App::new(&shader);
+    if let Err(err) = app.load_session() {
+        app.shader_error = Some(err);
+    }
     let (win, col) = viewport_map.get(&window_id).unwrap();
-    let mut renderer = Renderer::new(&mut [(win, *col)], Arc::clone(&contexts)).await?;
+    let mut renderer = Renderer::new(
+        shader,
+        mesh.as_deref(),
+        &mut [(win, *col)],
+        Arc::clone(&contexts),
+    )
+    .await?;
 
     let (sender, receiver) = channel();
-    let _watcher = watch_shader_files(sender)?;
+    let watcher = ShaderWatcher::new(&watch_config, sender)?;
+    if watcher.is_polling() {
+        println!("Polling for shader changes.");
+    }
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
@@ -93,24 +291,41 @@ async fn main() -> Result<()> {
                 }
             }
             Event::WindowEvent { window_id, event } => {
-                if let Some(ctx) = contexts.get(&window_id) {
-                    _ = egui_state
+                let consumed = contexts.get(&window_id).is_some_and(|ctx| {
+                    egui_state
                         .borrow_mut()
                         .lock()
                         .unwrap()
-                        .on_event(&ctx, &event);
-                }
-                app.handle_window_event(window_id, &event);
+                        .on_event(&ctx, &event)
+                        .consumed
+                });
+                app.handle_window_event(window_id, &event, consumed);
             }
             _ => (),
         }
 
         match receiver.try_recv() {
-            Ok(_events) => {
-                // Ok(DebouncedEvent::Write(_)) | Ok(DebouncedEvent::Create(_)) => {
-                println!("Shader file changed. Reloading shaders...");
-                let (_win, _col) = viewport_map.get(&window_id).unwrap();
-                renderer.reload().unwrap();
+            Ok(Err(errors)) => {
+                for err in errors {
+                    eprintln!("Watch error: {err}");
+                }
+                Ok(())
+            }
+            Ok(Ok(events)) => {
+                let mut changed: Vec<PathBuf> = vec![];
+                for event in events {
+                    if !watcher::is_temp_file(&event.path) && !changed.contains(&event.path) {
+                        changed.push(event.path);
+                    }
+                }
+                if !changed.is_empty() {
+                    for path in &changed {
+                        println!("{} changed. Reloading shaders...", path.display());
+                    }
+                    app.post_shaders = post::available_shaders(&app.post_dir);
+                    app.channel_files = channels::available_files(&app.channel_dir);
+                    renderer.reload(&changed);
+                }
                 Ok(())
             }
             Err(err) => match err {
@@ -0,0 +1,462 @@
+use std::path::Path;
+
+use glam::{Mat3, Mat4, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::shader::ShaderError;
+
+/// Which attributes a mesh's vertices have. Every vertex has a position; the
+/// rest are there if every part of the mesh had them. Vertex shaders read
+///
+/// ```wgsl
+/// @location(0) position: vec3<f32>,
+/// @location(1) normal: vec3<f32>,
+/// @location(2) uv: vec2<f32>,
+/// ```
+///
+/// and a shader that asks for an attribute the mesh doesn't have fails to
+/// build.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VertexFormat {
+    pub normals: bool,
+    pub uvs: bool,
+}
+
+impl VertexFormat {
+    /// The attributes in the order they're interleaved.
+    pub fn attributes(self) -> Vec<wgpu::VertexAttribute> {
+        let mut attributes = vec![];
+        let mut offset = 0;
+        let mut push = |location, format: wgpu::VertexFormat| {
+            attributes.push(wgpu::VertexAttribute {
+                format,
+                offset,
+                shader_location: location,
+            });
+            offset += format.size();
+        };
+        push(0, wgpu::VertexFormat::Float32x3);
+        if self.normals {
+            push(1, wgpu::VertexFormat::Float32x3);
+        }
+        if self.uvs {
+            push(2, wgpu::VertexFormat::Float32x2);
+        }
+        attributes
+    }
+
+    /// The size of a vertex in bytes.
+    pub fn stride(self) -> wgpu::BufferAddress {
+        (self.floats() * std::mem::size_of::<f32>()) as wgpu::BufferAddress
+    }
+
+    fn floats(self) -> usize {
+        3 + 3 * self.normals as usize + 2 * self.uvs as usize
+    }
+}
+
+/// Triangles with interleaved vertices, ready to go into buffers.
+pub struct Mesh {
+    format: VertexFormat,
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+}
+
+/// One OBJ model or glTF primitive, before the parts are merged.
+struct Part {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<[f32; 2]>>,
+    indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// The mesh drawn when none is loaded: a triangle with positions only.
+    pub fn triangle() -> Self {
+        Self::from_parts(vec![Part {
+            positions: vec![
+                Vec3::new(-0.5, -0.5, 0.0),
+                Vec3::new(0.5, -0.5, 0.0),
+                Vec3::new(0.0, 0.5, 0.0),
+            ],
+            normals: None,
+            uvs: None,
+            indices: vec![0, 1, 2],
+        }])
+    }
+
+    /// Load an `.obj`, `.gltf` or `.glb` file. Every model or primitive in it
+    /// ends up in the one mesh; glTF nodes are placed with their transforms.
+    pub fn load(path: &Path) -> Result<Self, ShaderError> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        let parts = match extension.as_deref() {
+            Some("obj") => load_obj(path)?,
+            Some("gltf" | "glb") => load_gltf(path)?,
+            _ => {
+                return Err(ShaderError::new(
+                    path,
+                    "unknown mesh type, expected .obj, .gltf or .glb",
+                ))
+            }
+        };
+        let mesh = Self::from_parts(parts);
+        if mesh.indices.is_empty() {
+            return Err(ShaderError::new(path, "the mesh has no triangles"));
+        }
+        Ok(mesh)
+    }
+
+    fn from_parts(parts: Vec<Part>) -> Self {
+        let format = VertexFormat {
+            normals: parts.iter().all(|part| part.normals.is_some()),
+            uvs: parts.iter().all(|part| part.uvs.is_some()),
+        };
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        for part in parts {
+            let base = (vertices.len() / format.floats()) as u32;
+            indices.extend(part.indices.iter().map(|index| base + index));
+            for (i, position) in part.positions.iter().enumerate() {
+                vertices.extend_from_slice(&position.to_array());
+                if let (true, Some(normals)) = (format.normals, &part.normals) {
+                    vertices.extend_from_slice(&normals[i].to_array());
+                }
+                if let (true, Some(uvs)) = (format.uvs, &part.uvs) {
+                    vertices.extend_from_slice(&uvs[i]);
+                }
+            }
+        }
+        Self {
+            format,
+            vertices,
+            indices,
+        }
+    }
+}
+
+fn load_obj(path: &Path) -> Result<Vec<Part>, ShaderError> {
+    // Materials are no use here, so a missing .mtl file isn't an error.
+    let (models, _) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+        .map_err(|err| ShaderError::new(path, format!("can't load mesh: {err}")))?;
+    let parts = models
+        .into_iter()
+        .map(|model| {
+            let mesh = model.mesh;
+            let count = mesh.positions.len() / 3;
+            Part {
+                positions: mesh
+                    .positions
+                    .chunks_exact(3)
+                    .map(Vec3::from_slice)
+                    .collect(),
+                normals: (mesh.normals.len() == 3 * count)
+                    .then(|| mesh.normals.chunks_exact(3).map(Vec3::from_slice).collect()),
+                // OBJ's V goes up, wgpu's texture coordinates go down.
+                uvs: (mesh.texcoords.len() == 2 * count).then(|| {
+                    mesh.texcoords
+                        .chunks_exact(2)
+                        .map(|uv| [uv[0], 1.0 - uv[1]])
+                        .collect()
+                }),
+                indices: mesh.indices,
+            }
+        })
+        .collect();
+    Ok(parts)
+}
+
+fn load_gltf(path: &Path) -> Result<Vec<Part>, ShaderError> {
+    let error = |message: String| ShaderError::new(path, format!("can't load mesh: {message}"));
+    let gltf = gltf::Gltf::open(path).map_err(|err| error(err.to_string()))?;
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut buffers = vec![];
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf
+                .blob
+                .clone()
+                .ok_or_else(|| error("the binary chunk is missing".to_owned()))?,
+            gltf::buffer::Source::Uri(uri) if uri.starts_with("data:") => {
+                return Err(error(
+                    "embedded buffers aren't supported, use .glb or a separate .bin file"
+                        .to_owned(),
+                ))
+            }
+            gltf::buffer::Source::Uri(uri) => std::fs::read(dir.join(uri))
+                .map_err(|err| error(format!("can't read {uri}: {err}")))?,
+        };
+        if data.len() < buffer.length() {
+            return Err(error(format!(
+                "buffer {} is shorter than its length",
+                buffer.index()
+            )));
+        }
+        buffers.push(data);
+    }
+
+    let mut parts = vec![];
+    let scene = gltf.default_scene().or_else(|| gltf.scenes().next());
+    match scene {
+        Some(scene) => {
+            for node in scene.nodes() {
+                add_node(&node, Mat4::IDENTITY, &buffers, &mut parts);
+            }
+        }
+        None => {
+            for mesh in gltf.meshes() {
+                add_mesh(&mesh, Mat4::IDENTITY, &buffers, &mut parts);
+            }
+        }
+    }
+    Ok(parts)
+}
+
+/// Add the meshes of `node` and its children, placed by their transforms.
+fn add_node(node: &gltf::Node, parent: Mat4, buffers: &[Vec<u8>], parts: &mut Vec<Part>) {
+    let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        add_mesh(&mesh, transform, buffers, parts);
+    }
+    for child in node.children() {
+        add_node(&child, transform, buffers, parts);
+    }
+}
+
+/// Add the triangle primitives of `mesh`. Lines and points are skipped, and
+/// so are normals and texture coordinates that don't have one per position.
+fn add_mesh(mesh: &gltf::Mesh, transform: Mat4, buffers: &[Vec<u8>], parts: &mut Vec<Part>) {
+    let normal_transform = Mat3::from_mat4(transform).inverse().transpose();
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            continue;
+        }
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+        let positions: Vec<_> = positions
+            .map(|position| transform.transform_point3(position.into()))
+            .collect();
+        let count = positions.len();
+        let normals: Option<Vec<_>> = reader.read_normals().map(|normals| {
+            normals
+                .map(|normal| (normal_transform * Vec3::from(normal)).normalize_or_zero())
+                .collect()
+        });
+        let uvs: Option<Vec<_>> = reader
+            .read_tex_coords(0)
+            .map(|uvs| uvs.into_f32().collect());
+        parts.push(Part {
+            positions,
+            normals: normals.filter(|normals| normals.len() == count),
+            uvs: uvs.filter(|uvs| uvs.len() == count),
+            indices: match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..count as u32).collect(),
+            },
+        });
+    }
+}
+
+/// A mesh's vertex and index buffers. Indices are 16-bit unless there are too
+/// many vertices for that.
+pub struct MeshBuffers {
+    pub format: VertexFormat,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_format: wgpu::IndexFormat,
+    num_indices: u32,
+}
+
+impl MeshBuffers {
+    pub fn new(device: &wgpu::Device, mesh: &Mesh) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(&mesh.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let (contents, index_format) = index_data(mesh);
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: &contents,
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            format: mesh.format,
+            vertex_buffer,
+            index_buffer,
+            index_format,
+            num_indices: mesh.indices.len() as u32,
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+}
+
+/// The mesh's indices as they go into the index buffer, and their format.
+fn index_data(mesh: &Mesh) -> (Vec<u8>, wgpu::IndexFormat) {
+    let vertex_count = mesh.vertices.len() / mesh.format.floats();
+    if vertex_count <= u16::MAX as usize + 1 {
+        let indices: Vec<u16> = mesh.indices.iter().map(|&index| index as u16).collect();
+        (
+            bytemuck::cast_slice(&indices).to_vec(),
+            wgpu::IndexFormat::Uint16,
+        )
+    } else {
+        (
+            bytemuck::cast_slice(&mesh.indices).to_vec(),
+            wgpu::IndexFormat::Uint32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(offset: f32, normals: bool) -> Part {
+        let positions: Vec<_> = (0..3).map(|i| Vec3::splat(offset + i as f32)).collect();
+        Part {
+            normals: normals.then(|| vec![Vec3::Z; 3]),
+            uvs: Some(vec![[0.0, 1.0]; 3]),
+            positions,
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    #[test]
+    fn vertex_formats_interleave_in_location_order() {
+        let full = VertexFormat {
+            normals: true,
+            uvs: true,
+        };
+        let offsets: Vec<_> = full
+            .attributes()
+            .iter()
+            .map(|attribute| (attribute.shader_location, attribute.offset))
+            .collect();
+        assert_eq!(offsets, [(0, 0), (1, 12), (2, 24)]);
+        assert_eq!(full.stride(), 32);
+
+        let uvs = VertexFormat {
+            normals: false,
+            uvs: true,
+        };
+        let offsets: Vec<_> = uvs
+            .attributes()
+            .iter()
+            .map(|attribute| (attribute.shader_location, attribute.offset))
+            .collect();
+        assert_eq!(offsets, [(0, 0), (2, 12)]);
+        assert_eq!(uvs.stride(), 20);
+        assert_eq!(VertexFormat::default().stride(), 12);
+    }
+
+    #[test]
+    fn parts_are_interleaved_and_their_indices_rebased() {
+        let mesh = Mesh::from_parts(vec![part(0.0, true), part(10.0, true)]);
+        assert_eq!(
+            mesh.format,
+            VertexFormat {
+                normals: true,
+                uvs: true,
+            }
+        );
+        assert_eq!(mesh.indices, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(mesh.vertices.len(), 6 * 8);
+        assert_eq!(mesh.vertices[..8], [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(mesh.vertices[3 * 8..3 * 8 + 3], [10.0, 10.0, 10.0]);
+    }
+
+    #[test]
+    fn attributes_only_some_parts_have_are_dropped() {
+        let mesh = Mesh::from_parts(vec![part(0.0, true), part(10.0, false)]);
+        assert_eq!(
+            mesh.format,
+            VertexFormat {
+                normals: false,
+                uvs: true,
+            }
+        );
+        assert_eq!(mesh.vertices.len(), 6 * 5);
+        assert_eq!(mesh.vertices[5..10], [1.0, 1.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn indices_are_16_bit_while_they_fit() {
+        let mesh = |vertex_count: usize| Mesh {
+            format: VertexFormat::default(),
+            vertices: vec![0.0; 3 * vertex_count],
+            indices: vec![0, 1, vertex_count as u32 - 1],
+        };
+        let (bytes, format) = index_data(&mesh(65536));
+        assert_eq!(format, wgpu::IndexFormat::Uint16);
+        assert_eq!(bytes, [0, 0, 1, 0, 0xff, 0xff]);
+        let (bytes, format) = index_data(&mesh(65537));
+        assert_eq!(format, wgpu::IndexFormat::Uint32);
+        assert_eq!(bytes[8..], [0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn obj_texture_coordinates_are_flipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quad.obj");
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0.25\nvt 0 1\nf 1/1 2/2 3/3\n";
+        std::fs::write(&path, obj).unwrap();
+        let mesh = Mesh::load(&path).unwrap();
+        assert_eq!(
+            mesh.format,
+            VertexFormat {
+                normals: false,
+                uvs: true,
+            }
+        );
+        let uvs: Vec<_> = mesh
+            .vertices
+            .chunks(5)
+            .map(|vertex| [vertex[3], vertex[4]])
+            .collect();
+        assert_eq!(uvs, [[0.0, 1.0], [1.0, 0.75], [0.0, 0.0]]);
+    }
+
+    #[test]
+    fn gltf_normals_that_dont_match_the_positions_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let positions = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let normals = [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0];
+        let mut data = bytemuck::cast_slice::<f32, u8>(&positions).to_vec();
+        data.extend_from_slice(bytemuck::cast_slice(&normals));
+        std::fs::write(dir.path().join("mesh.bin"), &data).unwrap();
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "uri": "mesh.bin", "byteLength": 60 }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 24 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 1, "componentType": 5126, "count": 2, "type": "VEC3" }
+            ],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "NORMAL": 1 } }] }]
+        }"#;
+        let path = dir.path().join("mesh.gltf");
+        std::fs::write(&path, gltf).unwrap();
+
+        let mesh = Mesh::load(&path).unwrap();
+        assert_eq!(mesh.format, VertexFormat::default());
+        assert_eq!(mesh.vertices, positions);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+    }
+}
@@ -0,0 +1,297 @@
+use std::{collections::BTreeMap, fmt, mem::discriminant};
+
+use serde::{Deserialize, Serialize};
+
+/// Values for a shader's `override` declarations, by name. Overrides that
+/// aren't in here keep the default from the declaration.
+pub type Constants = BTreeMap<String, OverrideValue>;
+
+/// The value of one pipeline-overridable constant. WGSL only allows scalars.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverrideValue {
+    Bool(bool),
+    I32(i32),
+    U32(u32),
+    F32(f32),
+}
+
+impl OverrideValue {
+    fn type_name(self) -> &'static str {
+        match self {
+            Self::Bool(_) => "bool",
+            Self::I32(_) => "i32",
+            Self::U32(_) => "u32",
+            Self::F32(_) => "f32",
+        }
+    }
+
+    fn zero(ty: &str) -> Option<Self> {
+        match ty {
+            "bool" => Some(Self::Bool(false)),
+            "i32" => Some(Self::I32(0)),
+            "u32" => Some(Self::U32(0)),
+            "f32" => Some(Self::F32(0.0)),
+            _ => None,
+        }
+    }
+
+    fn from_scalar(value: naga::ScalarValue) -> Self {
+        match value {
+            naga::ScalarValue::Bool(x) => Self::Bool(x),
+            naga::ScalarValue::Sint(x) => Self::I32(x as i32),
+            naga::ScalarValue::Uint(x) => Self::U32(x as u32),
+            naga::ScalarValue::Float(x) => Self::F32(x as f32),
+        }
+    }
+}
+
+/// As a WGSL literal of the right type.
+impl fmt::Display for OverrideValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(x) => write!(f, "{x}"),
+            Self::I32(x) => write!(f, "{x}i"),
+            Self::U32(x) => write!(f, "{x}u"),
+            Self::F32(x) => write!(f, "{x:?}f"),
+        }
+    }
+}
+
+/// One `override` declaration and the value it was compiled with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Override {
+    pub name: String,
+    pub value: OverrideValue,
+}
+
+/// wgpu can't set pipeline-overridable constants yet, so overrides are
+/// specialized in the source instead: a line like
+///
+/// ```wgsl
+/// @id(0) override radius: u32 = 4u;
+/// ```
+///
+/// becomes a `const` with the value from `constants`, or the declared default
+/// if there isn't one. Declarations have to fit on one line. Returns the
+/// rewritten line and the override's name, `None` if the line doesn't declare
+/// an override, or an error if it's malformed.
+pub fn specialize_line(
+    line: &str,
+    constants: &Constants,
+) -> Option<Result<(String, String), String>> {
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let mut rest = line.trim_start();
+    if let Some(after_id) = rest.strip_prefix("@id") {
+        let close = after_id.find(')')?;
+        rest = after_id[close + 1..].trim_start();
+    }
+    let rest = rest.strip_prefix("override")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(
+        specialize_declaration(rest, constants).map(|(declaration, name)| {
+            // Keep whatever followed the `;`, a comment or the newline.
+            let tail = &rest[rest.find(';').map_or(rest.len(), |end| end + 1)..];
+            (format!("{indent}{declaration}{tail}"), name)
+        }),
+    )
+}
+
+fn specialize_declaration(rest: &str, constants: &Constants) -> Result<(String, String), String> {
+    let declaration = rest
+        .split_once(';')
+        .ok_or("expected the override to end with `;` on the same line")?
+        .0;
+    let (head, default) = match declaration.split_once('=') {
+        Some((head, default)) => (head, Some(default.trim())),
+        None => (declaration, None),
+    };
+    let (name, ty) = match head.split_once(':') {
+        Some((name, ty)) => (name.trim(), Some(ty.trim())),
+        None => (head.trim(), None),
+    };
+    if name.is_empty() {
+        return Err("expected a name after `override`".to_owned());
+    }
+
+    let value = match (constants.get(name), ty) {
+        (Some(value), Some(ty)) if ty != value.type_name() => None,
+        (Some(value), _) => Some(*value),
+        (None, _) => None,
+    };
+    let declaration = match (value, ty, default) {
+        (Some(value), _, _) => format!("const {name}: {} = {value};", value.type_name()),
+        (None, Some(ty), Some(default)) => format!("const {name}: {ty} = {default};"),
+        (None, None, Some(default)) => format!("const {name} = {default};"),
+        (None, Some(ty), None) => {
+            let zero = OverrideValue::zero(ty)
+                .ok_or_else(|| format!("override {name} has unsupported type {ty}"))?;
+            format!("const {name}: {ty} = {zero};")
+        }
+        (None, None, None) => {
+            return Err(format!("override {name} needs a type or a default"));
+        }
+    };
+    Ok((declaration, name.to_owned()))
+}
+
+/// The values the overrides called `names` ended up with in `module`.
+pub fn reflect(module: &naga::Module, names: &[String]) -> Vec<Override> {
+    names
+        .iter()
+        .filter_map(|name| {
+            let value = module.constants.iter().find_map(|(_, constant)| {
+                match (&constant.name, &constant.inner) {
+                    (Some(n), naga::ConstantInner::Scalar { value, .. }) if n == name => {
+                        Some(OverrideValue::from_scalar(*value))
+                    }
+                    _ => None,
+                }
+            })?;
+            Some(Override {
+                name: name.clone(),
+                value,
+            })
+        })
+        .collect()
+}
+
+/// Whether both lists have the same overrides with the same types, whatever
+/// their values.
+pub fn same_layout(a: &[Override], b: &[Override]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(a, b)| a.name == b.name && discriminant(&a.value) == discriminant(&b.value))
+}
+
+/// `new`, with values carried over from `old` wherever an override kept its
+/// name and type.
+pub fn carry_over(new: &[Override], old: &[Override]) -> Vec<Override> {
+    new.iter()
+        .map(|new| {
+            let previous = old.iter().find(|old| {
+                old.name == new.name && discriminant(&old.value) == discriminant(&new.value)
+            });
+            previous.unwrap_or(new).clone()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specialize(
+        line: &str,
+        constants: &[(&str, OverrideValue)],
+    ) -> Option<Result<String, String>> {
+        let constants = constants
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        specialize_line(line, &constants).map(|result| result.map(|(line, _)| line))
+    }
+
+    #[test]
+    fn overrides_keep_their_defaults() {
+        assert_eq!(
+            specialize("@id(0) override radius: u32 = 4u;\n", &[]),
+            Some(Ok("const radius: u32 = 4u;\n".to_owned()))
+        );
+        assert_eq!(
+            specialize("    override scale = 1.5; // how big\n", &[]),
+            Some(Ok("    const scale = 1.5; // how big\n".to_owned()))
+        );
+    }
+
+    #[test]
+    fn overrides_take_values_from_constants() {
+        let constants = [
+            ("radius", OverrideValue::U32(8)),
+            ("scale", OverrideValue::F32(2.0)),
+            ("fog", OverrideValue::Bool(true)),
+            ("offset", OverrideValue::I32(-1)),
+        ];
+        let cases = [
+            ("override radius: u32 = 4u;", "const radius: u32 = 8u;"),
+            ("override scale = 1.5;", "const scale: f32 = 2.0f;"),
+            ("override fog: bool;", "const fog: bool = true;"),
+            ("override offset: i32 = 0i;", "const offset: i32 = -1i;"),
+        ];
+        for (line, specialized) in cases {
+            assert_eq!(
+                specialize(line, &constants),
+                Some(Ok(specialized.to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn constants_of_the_wrong_type_are_ignored() {
+        assert_eq!(
+            specialize(
+                "override radius: u32 = 4u;",
+                &[("radius", OverrideValue::F32(8.0))]
+            ),
+            Some(Ok("const radius: u32 = 4u;".to_owned()))
+        );
+    }
+
+    #[test]
+    fn overrides_without_a_default_are_zero() {
+        assert_eq!(
+            specialize("override scale: f32;", &[]),
+            Some(Ok("const scale: f32 = 0.0f;".to_owned()))
+        );
+    }
+
+    #[test]
+    fn other_lines_are_left_alone() {
+        for line in [
+            "const radius: u32 = 4u;",
+            "let overrides = 1;",
+            "overrider: u32,",
+            "// override radius: u32 = 4u;",
+            "",
+        ] {
+            assert_eq!(specialize(line, &[]), None, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_overrides_are_errors() {
+        let cases = [
+            (
+                "override radius: u32 = 4u",
+                "expected the override to end with `;` on the same line",
+            ),
+            ("override : u32;", "expected a name after `override`"),
+            (
+                "override radius;",
+                "override radius needs a type or a default",
+            ),
+            (
+                "override radius: vec2<f32>;",
+                "override radius has unsupported type vec2<f32>",
+            ),
+        ];
+        for (line, message) in cases {
+            assert_eq!(
+                specialize(line, &[]),
+                Some(Err(message.to_owned())),
+                "{line:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn specialized_lines_report_the_name() {
+        let (_, name) = specialize_line("@id(3) override radius: u32;", &Constants::new())
+            .unwrap()
+            .unwrap();
+        assert_eq!(name, "radius");
+    }
+}
@@ -0,0 +1,773 @@
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use toml::Spanned;
+use winit::dpi::PhysicalSize;
+
+use crate::channels::CHANNEL_COUNT;
+use crate::compiler::Builds;
+use crate::compute::{self, ComputePipeline};
+use crate::mesh::VertexFormat;
+use crate::overrides::Constants;
+use crate::preprocess::Defines;
+use crate::renderer::{self, PipelineSetup, ScenePipeline};
+use crate::shader::{self, Location, ShaderError};
+use crate::storage::{DeclaredBuffers, STORAGE_BUFFER_SLOTS, STORAGE_TEXTURE_SLOTS};
+
+/// Defined for shaders that draw into a buffer rather than to the screen.
+pub const BUFFER_PASS: &str = "BUFFER_PASS";
+
+/// Buffers keep more than 8 bits per channel so simulations don't lose
+/// precision from frame to frame, and can still be filtered.
+pub const BUFFER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The pass graph file that goes with `shader`: `name.passes.toml` next to
+/// `name.wgsl`.
+pub fn graph_path(shader: &Path) -> PathBuf {
+    shader.with_extension("passes.toml")
+}
+
+/// Offscreen passes run before the shader, like Shadertoy's buffer tabs, and
+/// compute passes with the storage they work on:
+///
+/// ```toml
+/// # What the shader itself samples on channels 0, 1, ...
+/// channels = ["A"]
+///
+/// [[buffer]]
+/// name = "A"
+/// shader = "fluid_a.glsl"
+/// channels = ["A", "B"]
+///
+/// [[buffer]]
+/// name = "B"
+/// shader = "fluid_b.glsl"
+/// channels = ["A"]
+/// ```
+///
+/// Buffers are drawn in order, each into a texture of its own the size of the
+/// window. A channel naming a buffer drawn earlier in the frame samples what it
+/// drew this frame; one naming the buffer itself or a later one samples what
+/// it drew last frame, which is how feedback works. Shader paths are relative
+/// to the graph file.
+///
+/// Shadertoy buffers keep Shadertoy's bottom-up rows, WGSL ones wgpu's
+/// top-down rows, so a graph should stick to one or the other.
+///
+/// Compute passes run first, in order, and see the storage declared next to
+/// them as described for `StorageBinding`:
+///
+/// ```toml
+/// [[storage]]
+/// name = "particles"
+/// size = 65536
+///
+/// [[storage]]
+/// name = "trail"
+///
+/// [[compute]]
+/// shader = "particles.wgsl"
+/// entry_point = "update"
+/// threads = [4096]
+///
+/// [[compute]]
+/// shader = "trail.wgsl"
+/// channels = ["trail"]
+/// ```
+///
+/// Storage with a `size` in bytes is a buffer, one without is a texture the
+/// size of the window that buffers and the shader sample on a channel. Storage
+/// textures are swapped every frame like buffers: compute passes write this
+/// frame's and sample last frame's, everything else samples this frame's. A
+/// compute pass runs `threads` invocations, up to three dimensions, or one
+/// per pixel if it doesn't say; its entry point is the first `@compute` one
+/// unless it names one.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PassGraph {
+    #[serde(default)]
+    pub channels: Vec<Spanned<String>>,
+    #[serde(default, rename = "buffer")]
+    pub buffers: Vec<Buffer>,
+    #[serde(default)]
+    pub storage: Vec<Storage>,
+    #[serde(default)]
+    pub compute: Vec<Compute>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Buffer {
+    pub name: Spanned<String>,
+    pub shader: PathBuf,
+    #[serde(default)]
+    pub channels: Vec<Spanned<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Storage {
+    pub name: Spanned<String>,
+    pub size: Option<Spanned<u64>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Compute {
+    pub shader: PathBuf,
+    pub entry_point: Option<String>,
+    pub threads: Option<Spanned<Vec<u32>>>,
+    #[serde(default)]
+    pub channels: Vec<Spanned<String>>,
+}
+
+/// A compute pipeline is built per shader and entry point.
+type ComputeKey = (PathBuf, Option<String>);
+
+impl Compute {
+    fn key(&self) -> ComputeKey {
+        (self.shader.clone(), self.entry_point.clone())
+    }
+
+    /// How many invocations to run at `size`.
+    pub fn threads(&self, size: PhysicalSize<u32>) -> [u32; 3] {
+        match &self.threads {
+            Some(threads) => {
+                let mut all = [1; 3];
+                all[..threads.get_ref().len()].copy_from_slice(threads.get_ref());
+                all
+            }
+            None => [size.width, size.height, 1],
+        }
+    }
+}
+
+/// Where a pass runs in the frame, for working out which of a texture's
+/// pair it samples.
+#[derive(Clone, Copy, Debug)]
+pub enum Pass {
+    Compute,
+    Buffer(usize),
+    Scene,
+}
+
+impl PassGraph {
+    /// Read the graph at `path`. No file is an empty graph.
+    pub fn load(path: &Path) -> Result<Self, ShaderError> {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(ShaderError::new(path, err.to_string())),
+        };
+        let error = |span: Option<Range<usize>>, message: String| ShaderError {
+            path: path.to_owned(),
+            location: span.map(|span| Location::at(&source, span)),
+            message,
+            source: source.clone(),
+        };
+
+        let mut graph: Self =
+            toml::from_str(&source).map_err(|err| error(err.span(), err.message().to_owned()))?;
+        let names: Vec<_> = graph
+            .buffers
+            .iter()
+            .map(|buffer| &buffer.name)
+            .chain(graph.storage.iter().map(|storage| &storage.name))
+            .collect();
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                let message = format!("there's already something called {}", name.get_ref());
+                return Err(error(Some(name.span()), message));
+            }
+        }
+        let (buffers, textures): (Vec<_>, Vec<_>) = graph
+            .storage
+            .iter()
+            .partition(|storage| storage.size.is_some());
+        for (storage, slots) in [
+            (buffers, STORAGE_BUFFER_SLOTS),
+            (textures, STORAGE_TEXTURE_SLOTS),
+        ] {
+            if let Some(extra) = storage.get(slots as usize) {
+                let kind = if extra.size.is_some() {
+                    "buffers"
+                } else {
+                    "textures"
+                };
+                let message = format!("there are only {slots} storage {kind}");
+                return Err(error(Some(extra.name.span()), message));
+            }
+        }
+        for size in graph
+            .storage
+            .iter()
+            .filter_map(|storage| storage.size.as_ref())
+        {
+            if *size.get_ref() == 0 || size.get_ref() % 4 != 0 {
+                let message = "storage buffer sizes are a multiple of 4 bytes".to_owned();
+                return Err(error(Some(size.span()), message));
+            }
+        }
+        for threads in graph
+            .compute
+            .iter()
+            .filter_map(|compute| compute.threads.as_ref())
+        {
+            if !(1..=3).contains(&threads.get_ref().len()) {
+                let message = "threads has one to three dimensions".to_owned();
+                return Err(error(Some(threads.span()), message));
+            }
+        }
+
+        let lists = graph
+            .buffers
+            .iter()
+            .map(|buffer| &buffer.channels)
+            .chain(graph.compute.iter().map(|compute| &compute.channels));
+        for channels in lists.chain([&graph.channels]) {
+            if let Some(extra) = channels.get(CHANNEL_COUNT as usize) {
+                let message = format!("there are only {CHANNEL_COUNT} channels");
+                return Err(error(Some(extra.span()), message));
+            }
+            for channel in channels {
+                if !graph.has_texture(channel.get_ref()) {
+                    let message = format!(
+                        "there's no buffer or storage texture called {}",
+                        channel.get_ref()
+                    );
+                    return Err(error(Some(channel.span()), message));
+                }
+            }
+        }
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        for buffer in &mut graph.buffers {
+            buffer.shader = dir.join(&buffer.shader);
+        }
+        for compute in &mut graph.compute {
+            compute.shader = dir.join(&compute.shader);
+        }
+        Ok(graph)
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.buffers
+            .iter()
+            .position(|buffer| buffer.name.get_ref() == name)
+    }
+
+    /// The storage textures, in binding order.
+    fn storage_textures(&self) -> impl Iterator<Item = &String> {
+        self.storage
+            .iter()
+            .filter(|storage| storage.size.is_none())
+            .map(|storage| storage.name.get_ref())
+    }
+
+    /// The storage buffers and their sizes, in binding order.
+    pub fn storage_buffers(&self) -> impl Iterator<Item = (&String, u64)> {
+        self.storage.iter().filter_map(|storage| {
+            let size = storage.size.as_ref()?;
+            Some((storage.name.get_ref(), *size.get_ref()))
+        })
+    }
+
+    /// Whether `name` is a buffer or a storage texture.
+    fn has_texture(&self, name: &str) -> bool {
+        self.index(name).is_some() || self.storage_textures().any(|texture| texture == name)
+    }
+
+    /// Whether `name` has been drawn or written this frame by the time `pass`
+    /// runs.
+    fn written_before(&self, name: &str, pass: Pass) -> bool {
+        match (self.index(name), pass) {
+            (_, Pass::Compute) => false,
+            (Some(buffer), Pass::Buffer(index)) => buffer < index,
+            _ => true,
+        }
+    }
+}
+
+/// The textures a graph's buffers draw into and its storage, for one viewport.
+/// Each texture comes in a pair, swapped every frame, so a pass can read last
+/// frame's while writing this frame's.
+#[derive(Default)]
+pub struct BufferTargets {
+    size: PhysicalSize<u32>,
+    textures: HashMap<String, [wgpu::Texture; 2]>,
+    storage_buffers: HashMap<String, wgpu::Buffer>,
+    /// Which of each pair is written this frame.
+    current: usize,
+}
+
+impl BufferTargets {
+    /// Make sure every buffer and storage texture in `graph` has textures of
+    /// `size`, and every storage buffer exists at its size. New ones start out
+    /// zeroed.
+    pub fn update(&mut self, device: &wgpu::Device, graph: &PassGraph, size: PhysicalSize<u32>) {
+        if size != self.size {
+            self.resize(device, size);
+        }
+        self.textures.retain(|name, _| graph.has_texture(name));
+        let names = graph
+            .buffers
+            .iter()
+            .map(|buffer| buffer.name.get_ref())
+            .chain(graph.storage_textures());
+        for name in names {
+            if !self.textures.contains_key(name) {
+                let textures = create_textures(device, size, name);
+                self.textures.insert(name.clone(), textures);
+            }
+        }
+
+        let sizes: HashMap<_, _> = graph.storage_buffers().collect();
+        self.storage_buffers
+            .retain(|name, buffer| sizes.get(name) == Some(&buffer.size()));
+        for (name, size) in sizes {
+            if !self.storage_buffers.contains_key(name) {
+                let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(name),
+                    size,
+                    usage: wgpu::BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                });
+                self.storage_buffers.insert(name.clone(), buffer);
+            }
+        }
+    }
+
+    /// Start every texture over at `size`. What they held is lost, like it is
+    /// on Shadertoy. Storage buffers don't depend on the size and are kept.
+    pub fn resize(&mut self, device: &wgpu::Device, size: PhysicalSize<u32>) {
+        self.size = size;
+        for (name, textures) in &mut self.textures {
+            for old in std::mem::replace(textures, create_textures(device, size, name)) {
+                old.destroy();
+            }
+        }
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Make this frame's textures last frame's.
+    pub fn swap(&mut self) {
+        self.current = 1 - self.current;
+    }
+
+    /// The texture `name` is written to this frame.
+    pub fn target(&self, name: &str) -> wgpu::TextureView {
+        self.view(name, self.current)
+    }
+
+    /// What `channels` sample in `pass`: this frame's texture for what's been
+    /// written before it, last frame's for the rest.
+    pub fn channel_views(
+        &self,
+        graph: &PassGraph,
+        channels: &[Spanned<String>],
+        pass: Pass,
+    ) -> Vec<wgpu::TextureView> {
+        channels
+            .iter()
+            .map(|channel| {
+                let name = channel.get_ref();
+                if graph.written_before(name, pass) {
+                    self.view(name, self.current)
+                } else {
+                    self.view(name, 1 - self.current)
+                }
+            })
+            .collect()
+    }
+
+    /// The storage buffers, in binding order.
+    pub fn storage_buffers(&self, graph: &PassGraph) -> Vec<&wgpu::Buffer> {
+        graph
+            .storage_buffers()
+            .map(|(name, _)| &self.storage_buffers[name])
+            .collect()
+    }
+
+    /// This frame's storage textures, in binding order.
+    pub fn storage_textures(&self, graph: &PassGraph) -> Vec<wgpu::TextureView> {
+        graph
+            .storage_textures()
+            .map(|name| self.target(name))
+            .collect()
+    }
+
+    fn view(&self, name: &str, which: usize) -> wgpu::TextureView {
+        self.textures[name][which].create_view(&wgpu::TextureViewDescriptor::default())
+    }
+}
+
+fn create_textures(
+    device: &wgpu::Device,
+    size: PhysicalSize<u32>,
+    name: &str,
+) -> [wgpu::Texture; 2] {
+    [0, 1].map(|_| {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(name),
+            size: wgpu::Extent3d {
+                width: size.width.max(1),
+                height: size.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: BUFFER_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        })
+    })
+}
+
+/// The pass graph of the shader being played with and the pipelines its
+/// passes run. Buffer shaders are built like the scene, with `BUFFER_PASS`
+/// defined, and every pass's uniform is at its default values. Until a pass's
+/// pipeline is ready it's skipped.
+pub struct Passes {
+    graph_path: PathBuf,
+    pub graph: PassGraph,
+    /// The layouts of `@group(1)` on for buffers.
+    render_layouts: [Arc<wgpu::BindGroupLayout>; 3],
+    /// The layouts of `@group(1)` on for compute passes.
+    compute_layouts: [Arc<wgpu::BindGroupLayout>; 3],
+    /// The mesh buffers with a vertex stage draw.
+    vertex_format: VertexFormat,
+    buffers: Builds<PathBuf, ScenePipeline>,
+    compute: Builds<ComputeKey, ComputePipeline>,
+    /// A graph file that didn't load, reported by the next `prepare`. The
+    /// last good graph keeps running.
+    error: Option<ShaderError>,
+}
+
+impl Passes {
+    pub fn new(
+        shader_path: &Path,
+        render_layouts: [Arc<wgpu::BindGroupLayout>; 3],
+        compute_layouts: [Arc<wgpu::BindGroupLayout>; 3],
+        vertex_format: VertexFormat,
+    ) -> Result<Self, ShaderError> {
+        let graph_path = graph_path(shader_path);
+        let graph = PassGraph::load(&graph_path)?;
+        Ok(Self {
+            graph_path,
+            graph,
+            render_layouts,
+            compute_layouts,
+            vertex_format,
+            buffers: Builds::new(),
+            compute: Builds::new(),
+            error: None,
+        })
+    }
+
+    /// Start building pipelines for passes that don't have one yet, and swap
+    /// in the builds that have finished. With `wait`, blocks until every build
+    /// is done. Returns the first failure.
+    pub fn prepare(&mut self, device: &Arc<wgpu::Device>, wait: bool) -> Result<(), ShaderError> {
+        let paths: Vec<_> = self
+            .graph
+            .buffers
+            .iter()
+            .map(|buffer| buffer.shader.clone())
+            .collect();
+        for path in paths {
+            // Checked as they're started, as buffers can share a shader.
+            if self.buffers.is_new(&path) {
+                self.build_buffer(device, path);
+            }
+        }
+        let keys: Vec<_> = self.graph.compute.iter().map(Compute::key).collect();
+        for key in keys {
+            if self.compute.is_new(&key) {
+                self.build_compute(device, key);
+            }
+        }
+
+        let result = self.error.take().map_or(Ok(()), Err);
+        result
+            .and(self.buffers.finish(wait))
+            .and(self.compute.finish(wait))
+    }
+
+    /// Reread the graph if its file is one of `changed`, and start rebuilding
+    /// the pipelines whose shader, or anything it includes, is.
+    pub fn reload(&mut self, device: &Arc<wgpu::Device>, changed: &[PathBuf]) {
+        if changed
+            .iter()
+            .any(|path| shader::same_file(path, &self.graph_path))
+        {
+            match PassGraph::load(&self.graph_path) {
+                Ok(graph) => self.graph = graph,
+                Err(err) => self.error = Some(err),
+            }
+        }
+
+        for path in self.buffers.outdated(changed) {
+            self.build_buffer(device, path);
+        }
+        for key in self.compute.outdated(changed) {
+            self.build_compute(device, key);
+        }
+    }
+
+    pub fn is_compiling(&self) -> bool {
+        self.buffers.is_busy() || self.compute.is_busy()
+    }
+
+    pub fn buffer_pipeline(&self, buffer: &Buffer) -> Option<&ScenePipeline> {
+        self.buffers.get(&buffer.shader)
+    }
+
+    pub fn compute_pipeline(&self, compute: &Compute) -> Option<&ComputePipeline> {
+        self.compute.get(&compute.key())
+    }
+
+    /// The storage buffers of the graph, for pipelines to be checked against.
+    /// Every pipeline is rebuilt when the graph changes.
+    pub fn declared_buffers(&self) -> DeclaredBuffers {
+        DeclaredBuffers {
+            graph_path: self.graph_path.clone(),
+            buffers: self
+                .graph
+                .storage_buffers()
+                .map(|(name, size)| (name.clone(), size))
+                .collect(),
+        }
+    }
+
+    fn build_buffer(&mut self, device: &Arc<wgpu::Device>, path: PathBuf) {
+        let device = Arc::clone(device);
+        let layouts = self.render_layouts.clone();
+        let vertex_format = self.vertex_format;
+        let storage = self.declared_buffers();
+        self.buffers.spawn(path.clone(), move |files| {
+            let setup = PipelineSetup {
+                color_target: BUFFER_FORMAT.into(),
+                shared_layouts: &layouts.each_ref().map(Arc::as_ref),
+                vertex_format,
+                depth: false,
+                storage: &storage,
+            };
+            shader::catch_panic(&path, || {
+                renderer::create_pipeline(
+                    &device,
+                    setup,
+                    &path,
+                    &Defines::from([BUFFER_PASS.to_owned()]),
+                    &Constants::new(),
+                    files,
+                )
+            })
+        });
+    }
+
+    fn build_compute(&mut self, device: &Arc<wgpu::Device>, key: ComputeKey) {
+        let device = Arc::clone(device);
+        let layouts = self.compute_layouts.clone();
+        let storage = self.declared_buffers();
+        self.compute.spawn(key.clone(), move |files| {
+            let (path, entry_point) = key;
+            shader::catch_panic(&path, || {
+                compute::create_pipeline(
+                    &device,
+                    &layouts.each_ref().map(Arc::as_ref),
+                    &storage,
+                    &path,
+                    entry_point.as_deref(),
+                    files,
+                )
+            })
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(source: &str) -> Result<PassGraph, ShaderError> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shader.passes.toml");
+        std::fs::write(&path, source).unwrap();
+        PassGraph::load(&path)
+    }
+
+    /// The error for `source`, with the text its location points at.
+    fn error(source: &str) -> (String, String) {
+        let err = load(source).unwrap_err();
+        let location = err.location.expect("the error has a location");
+        let start = location.offset as usize;
+        let marked = source[start..start + location.length as usize].to_owned();
+        (err.message, marked)
+    }
+
+    #[test]
+    fn a_missing_graph_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let graph = PassGraph::load(&dir.path().join("shader.passes.toml")).unwrap();
+        assert!(graph.buffers.is_empty() && graph.compute.is_empty());
+    }
+
+    #[test]
+    fn shader_paths_are_relative_to_the_graph() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shader.passes.toml");
+        let source = "
+channels = [\"A\"]
+
+[[buffer]]
+name = \"A\"
+shader = \"a.wgsl\"
+channels = [\"A\"]
+
+[[compute]]
+shader = \"sim.wgsl\"
+threads = [64, 2]
+";
+        std::fs::write(&path, source).unwrap();
+        let graph = PassGraph::load(&path).unwrap();
+        assert_eq!(graph.buffers[0].shader, dir.path().join("a.wgsl"));
+        assert_eq!(graph.compute[0].shader, dir.path().join("sim.wgsl"));
+        assert_eq!(
+            graph.compute[0].threads(PhysicalSize::new(800, 600)),
+            [64, 2, 1]
+        );
+    }
+
+    #[test]
+    fn toml_errors_point_into_the_file() {
+        let (message, marked) = error("[[buffer]]\nname = \"A\"\nshader = \"a.wgsl\"\nsize = 4\n");
+        assert!(message.contains("unknown field `size`"), "{message}");
+        assert_eq!(marked, "size");
+    }
+
+    #[test]
+    fn names_must_be_unique() {
+        let source = "
+[[buffer]]
+name = \"A\"
+shader = \"a.wgsl\"
+
+[[storage]]
+name = \"A\"
+";
+        let (message, marked) = error(source);
+        assert_eq!(message, "there's already something called A");
+        assert_eq!(marked, "\"A\"");
+        assert_eq!(load(source).unwrap_err().location.unwrap().line, 7);
+    }
+
+    #[test]
+    fn channels_must_name_a_texture() {
+        let (message, marked) = error("channels = [\"A\"]\n");
+        assert_eq!(message, "there's no buffer or storage texture called A");
+        assert_eq!(marked, "\"A\"");
+
+        let source = "
+[[storage]]
+name = \"particles\"
+size = 16
+
+[[compute]]
+shader = \"sim.wgsl\"
+channels = [\"particles\"]
+";
+        let (message, _) = error(source);
+        assert_eq!(
+            message,
+            "there's no buffer or storage texture called particles"
+        );
+    }
+
+    #[test]
+    fn channels_are_limited() {
+        let source = "
+channels = [\"A\", \"A\", \"A\", \"A\", \"B\"]
+
+[[buffer]]
+name = \"A\"
+shader = \"a.wgsl\"
+";
+        let (message, marked) = error(source);
+        assert_eq!(message, "there are only 4 channels");
+        assert_eq!(marked, "\"B\"");
+    }
+
+    #[test]
+    fn storage_is_limited() {
+        let buffers: String = (0..5)
+            .map(|i| format!("[[storage]]\nname = \"b{i}\"\nsize = 16\n"))
+            .collect();
+        let (message, marked) = error(&buffers);
+        assert_eq!(message, "there are only 4 storage buffers");
+        assert_eq!(marked, "\"b4\"");
+
+        let textures: String = (0..5)
+            .map(|i| format!("[[storage]]\nname = \"t{i}\"\n"))
+            .collect();
+        let (message, marked) = error(&textures);
+        assert_eq!(message, "there are only 4 storage textures");
+        assert_eq!(marked, "\"t4\"");
+    }
+
+    #[test]
+    fn storage_buffer_sizes_are_whole_words() {
+        for size in ["0", "6"] {
+            let (message, marked) = error(&format!("[[storage]]\nname = \"b\"\nsize = {size}\n"));
+            assert_eq!(message, "storage buffer sizes are a multiple of 4 bytes");
+            assert_eq!(marked, size);
+        }
+    }
+
+    #[test]
+    fn threads_have_one_to_three_dimensions() {
+        for threads in ["[]", "[1, 2, 3, 4]"] {
+            let (message, marked) = error(&format!(
+                "[[compute]]\nshader = \"sim.wgsl\"\nthreads = {threads}\n"
+            ));
+            assert_eq!(message, "threads has one to three dimensions");
+            assert_eq!(marked, threads);
+        }
+    }
+
+    #[test]
+    fn buffers_before_a_pass_are_read_from_this_frame() {
+        let graph = load(
+            "
+[[buffer]]
+name = \"A\"
+shader = \"a.wgsl\"
+
+[[buffer]]
+name = \"B\"
+shader = \"b.wgsl\"
+
+[[storage]]
+name = \"trail\"
+",
+        )
+        .unwrap();
+        assert!(!graph.written_before("A", Pass::Buffer(0)));
+        assert!(graph.written_before("A", Pass::Buffer(1)));
+        assert!(!graph.written_before("B", Pass::Buffer(1)));
+        assert!(graph.written_before("B", Pass::Scene));
+        assert!(!graph.written_before("trail", Pass::Compute));
+        assert!(graph.written_before("trail", Pass::Buffer(0)));
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use wgpu::util::DeviceExt;
+
+use crate::compiler::Builds;
+use crate::overrides::Constants;
+use crate::preprocess::Defines;
+use crate::shader::{self, ShaderError};
+use crate::uniforms::{Control, UniformBlock, UniformField, UniformValue, Widget};
+
+/// Where the UI looks for shaders that can be added to the chain, relative to
+/// the directory of the shader being played with.
+pub const POST_SHADER_DIR: &str = "post";
+
+/// The post shader directory that goes with `shader`.
+pub fn shader_dir(shader: &Path) -> PathBuf {
+    shader
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(POST_SHADER_DIR)
+}
+
+/// One step of the post-processing chain, as edited in the UI.
+///
+/// A post shader gets the previous step's output at `@binding(0)`, a sampler
+/// at `@binding(1)` and its params as a uniform at `@binding(2)`, and draws a
+/// fullscreen triangle from `vs_main`/`fs_main`. The globals are at
+/// `@group(1)` like everywhere else. The params are reflected like a scene
+/// shader's uniform, `@ui` annotations included, once the pass's pipeline is
+/// built.
+#[derive(Clone, Debug)]
+pub struct PostPass {
+    pub name: String,
+    pub shader: PathBuf,
+    pub enabled: bool,
+    pub params: UniformBlock,
+}
+
+impl PostPass {
+    pub fn new(shader: impl Into<PathBuf>) -> Self {
+        let shader = shader.into();
+        let name = shader
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self {
+            name,
+            shader,
+            enabled: true,
+            params: UniformBlock::default(),
+        }
+    }
+}
+
+/// The chain a new session starts with: a separable blur, switched off. Empty
+/// if `dir` has no blur shader.
+pub fn default_chain(dir: &Path) -> Vec<PostPass> {
+    let blur = dir.join("blur.wgsl");
+    if !blur.is_file() {
+        return vec![];
+    }
+    vec![
+        PostPass {
+            name: "blur horizontal".to_owned(),
+            enabled: false,
+            params: blur_direction([1.0, 0.0]),
+            ..PostPass::new(&blur)
+        },
+        PostPass {
+            name: "blur vertical".to_owned(),
+            enabled: false,
+            params: blur_direction([0.0, 1.0]),
+            ..PostPass::new(&blur)
+        },
+    ]
+}
+
+/// Params with just the blur's `direction` set. The rest comes from the
+/// shader once it's reflected, which keeps the direction.
+fn blur_direction(direction: [f32; 2]) -> UniformBlock {
+    let direction = UniformField {
+        name: "direction".to_owned(),
+        offset: 0,
+        control: Control {
+            widget: Widget::Drag,
+            range: None,
+            step: 0.01,
+        },
+        value: UniformValue::Vec2(direction),
+    };
+    UniformBlock {
+        fields: vec![direction],
+        ..UniformBlock::default()
+    }
+}
+
+/// The shaders in `dir`, sorted by path.
+pub fn available_shaders(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| shader::is_shader(path))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// The GPU side of the chain. Pipelines are built in the background per shader
+/// file the first time a pass uses it, so the same shader can run several
+/// times with different params. A pass is skipped until its pipeline is ready.
+pub struct PostChain {
+    format: wgpu::TextureFormat,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    sampler: wgpu::Sampler,
+    pipelines: Builds<PathBuf, PostPipeline>,
+}
+
+struct PostPipeline {
+    pipeline: wgpu::RenderPipeline,
+    /// The shader's params, reflected.
+    params: UniformBlock,
+}
+
+impl PostChain {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        globals_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        // Each shader's params have their own size.
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Post Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout, globals_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            format,
+            bind_group_layout,
+            pipeline_layout,
+            sampler,
+            pipelines: Builds::new(),
+        }
+    }
+
+    /// Start building pipelines for any shaders in `passes` that haven't been
+    /// seen yet, and swap in the builds that have finished. With `wait`, blocks
+    /// until every build is done. Each pass's params then follow its shader,
+    /// keeping the values of members that survived a reload. Returns the
+    /// first failure.
+    pub fn prepare(
+        &mut self,
+        device: &Arc<wgpu::Device>,
+        passes: &mut [PostPass],
+        wait: bool,
+    ) -> Result<(), ShaderError> {
+        for pass in passes.iter() {
+            if self.pipelines.is_new(&pass.shader) {
+                self.build(device, pass.shader.clone());
+            }
+        }
+        let result = self.pipelines.finish(wait);
+        for pass in passes {
+            if let Some(pipeline) = self.pipelines.get(&pass.shader) {
+                if !pass.params.layout_matches(&pipeline.params) {
+                    pass.params = pipeline.params.with_values_from(&pass.params);
+                }
+            }
+        }
+        result
+    }
+
+    /// Start rebuilding the known pipelines whose shader, or anything it
+    /// includes, is one of `changed`. The results come in through `prepare`.
+    pub fn reload(&mut self, device: &Arc<wgpu::Device>, changed: &[PathBuf]) {
+        for path in self.pipelines.outdated(changed) {
+            self.build(device, path);
+        }
+    }
+
+    pub fn is_compiling(&self) -> bool {
+        self.pipelines.is_busy()
+    }
+
+    /// Whether any pass will actually run, i.e. whether the scene needs to go
+    /// offscreen first.
+    pub fn is_active(&self, passes: &[PostPass]) -> bool {
+        passes.iter().any(|pass| self.pipeline(pass).is_some())
+    }
+
+    /// Run the enabled passes in order. The scene is expected in `textures[0]`;
+    /// the passes ping-pong between the two textures and the last one writes
+    /// to `output`.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        passes: &[PostPass],
+        globals: &wgpu::BindGroup,
+        textures: [&wgpu::TextureView; 2],
+        output: &wgpu::TextureView,
+    ) {
+        let active: Vec<_> = passes
+            .iter()
+            .filter_map(|pass| Some((pass, self.pipeline(pass)?)))
+            .collect();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Post Encoder"),
+        });
+        for (i, (pass, pipeline)) in active.iter().enumerate() {
+            let source = textures[i % 2];
+            let target = if i + 1 == active.len() {
+                output
+            } else {
+                textures[(i + 1) % 2]
+            };
+
+            let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Post Params Buffer"),
+                contents: &pass.params.to_bytes(),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Post Bind Group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(&pass.name),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.set_bind_group(1, globals, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+
+    fn pipeline(&self, pass: &PostPass) -> Option<&wgpu::RenderPipeline> {
+        if !pass.enabled {
+            return None;
+        }
+        Some(&self.pipelines.get(&pass.shader)?.pipeline)
+    }
+
+    fn build(&mut self, device: &Arc<wgpu::Device>, path: PathBuf) {
+        let device = Arc::clone(device);
+        let layout = Arc::clone(&self.pipeline_layout);
+        let format = self.format;
+        self.pipelines.spawn(path.clone(), move |files| {
+            shader::catch_panic(&path, || {
+                create_pipeline(&device, &layout, format, &path, files)
+            })
+        });
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    path: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<PostPipeline, ShaderError> {
+    let loaded = shader::load_shader(path, &Defines::new(), &Constants::new(), files)?;
+    let (vertex, fragment) = loaded.stages(path)?;
+    let binding = naga::ResourceBinding {
+        group: 0,
+        binding: 2,
+    };
+    let params = UniformBlock::reflect_binding(&loaded.module, &loaded.source, binding);
+    let pipeline = shader::capture_errors(device, path, || {
+        let vertex_module = vertex.create_module(device, "Post Vertex Shader");
+        let fragment_module = fragment.create_module(device, "Post Fragment Shader");
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Post Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &vertex_module,
+                entry_point: &vertex.entry_point,
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment_module,
+                entry_point: &fragment.entry_point,
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    })?;
+    Ok(PostPipeline { pipeline, params })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_blur_params_are_reflected_and_keep_their_direction() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/shaders/post/blur.wgsl");
+        let loaded =
+            shader::load_shader(&path, &Defines::new(), &Constants::new(), &mut vec![]).unwrap();
+        let binding = naga::ResourceBinding {
+            group: 0,
+            binding: 2,
+        };
+        let reflected = UniformBlock::reflect_binding(&loaded.module, &loaded.source, binding);
+        assert!(reflected.warnings.is_empty(), "{:?}", reflected.warnings);
+
+        let params = reflected.with_values_from(&blur_direction([0.0, 1.0]));
+        let values: Vec<_> = params
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.value.clone()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("direction", UniformValue::Vec2([0.0, 1.0])),
+                ("kernel_radius", UniformValue::F32(8.0)),
+            ]
+        );
+        assert_eq!(params.fields[1].control.widget, Widget::Slider);
+        assert_eq!(params.fields[1].control.range, Some(0.0..=120.0));
+    }
+}
@@ -0,0 +1,652 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::overrides::{self, Constants};
+use crate::shader::{Location, ShaderError};
+
+/// The names defined before a shader is expanded, i.e. one permutation of it.
+pub type Defines = BTreeSet<String>;
+
+/// A shader with its preprocessor directives applied, plus enough bookkeeping
+/// to point errors in the result back at the file and line they came from.
+///
+/// `#include "path"` is replaced by the named file. Included paths are
+/// relative to the directory of the shader being expanded. Each file is
+/// included at most once, so shared helpers can include each other freely;
+/// including a file that is still being expanded is a cycle and an error.
+/// `#import` is accepted as a synonym for `#include`.
+///
+/// `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` keep or drop lines
+/// depending on whether `NAME` is defined, either up front or by a `#define
+/// NAME` earlier in the source. `#undef NAME` forgets a name again.
+/// Conditionals have to be closed in the file that opens them.
+///
+/// `override` declarations become `const`s, see `overrides::specialize_line`.
+///
+/// GLSL has a preprocessor of its own, so there only `#include` is handled and
+/// everything else is left to naga, see `expand_glsl`.
+#[derive(Debug)]
+pub struct Expanded {
+    pub text: String,
+    /// Every name tested by an `#ifdef` or `#ifndef`, in order of first use.
+    /// These are the switches that pick a permutation.
+    pub switches: Vec<String>,
+    /// The names of the `override` declarations that were kept.
+    pub overrides: Vec<String>,
+    lines: Vec<LineOrigin>,
+    /// Lines added in front of everything in `lines` by `wrap`.
+    header_lines: usize,
+    /// Every file that went into `text` with its original contents. The shader
+    /// itself comes first.
+    files: Vec<(PathBuf, String)>,
+}
+
+/// Where one line of the expanded text came from.
+#[derive(Debug)]
+struct LineOrigin {
+    /// Byte offset of the line in the expanded text.
+    offset: usize,
+    /// Index into `Expanded::files`.
+    file: usize,
+    /// 1-based line number in that file.
+    line: u32,
+    /// Byte offset of the line in that file.
+    file_offset: usize,
+}
+
+impl Expanded {
+    /// Move an error's location from the expanded text to the file it came
+    /// from, with that file's source for the excerpt.
+    pub fn map_error(&self, mut err: ShaderError) -> ShaderError {
+        let Some(loc) = err.location else {
+            return err;
+        };
+        let origin = (loc.line as usize)
+            .checked_sub(1 + self.header_lines)
+            .and_then(|index| self.lines.get(index));
+        let Some(origin) = origin else {
+            return err;
+        };
+        let (path, source) = &self.files[origin.file];
+        err.path = path.clone();
+        err.source = source.clone();
+        err.location = Some(Location {
+            line: origin.line,
+            offset: (origin.file_offset + (loc.offset as usize).saturating_sub(origin.offset))
+                as u32,
+            ..loc
+        });
+        err
+    }
+
+    /// Put `header` in front of the text and `footer` after it. Errors in
+    /// either stay pointing at the expanded text.
+    pub fn wrap(&mut self, header: &str, footer: &str) {
+        for line in &mut self.lines {
+            line.offset += header.len();
+        }
+        self.header_lines += header.lines().count();
+        self.text.insert_str(0, header);
+        self.text.push_str(footer);
+    }
+}
+
+/// Expand `path` with `defines` defined and overrides set to `constants`.
+/// Every file read, or that would have been read, is added to `files` even
+/// when expansion fails, so the caller knows what to watch.
+pub fn expand(
+    path: &Path,
+    defines: &Defines,
+    constants: &Constants,
+    files: &mut Vec<PathBuf>,
+) -> Result<Expanded, ShaderError> {
+    Expander::new(path, defines, constants, files, false).run(path)
+}
+
+/// Expand the includes of a GLSL shader. Other directives are kept for naga's
+/// GLSL preprocessor, which also knows `#if`, `#define` with a value and
+/// macros; the caller passes it the defines. The names `#ifdef` and `#ifndef`
+/// test are still collected as switches.
+pub fn expand_glsl(path: &Path, files: &mut Vec<PathBuf>) -> Result<Expanded, ShaderError> {
+    let constants = Constants::new();
+    Expander::new(path, &Defines::new(), &constants, files, true).run(path)
+}
+
+struct Expander<'a> {
+    dir: PathBuf,
+    expanded: Expanded,
+    defines: Defines,
+    constants: &'a Constants,
+    /// Only handle includes, for GLSL.
+    includes_only: bool,
+    /// Files currently being expanded, outermost first.
+    stack: Vec<PathBuf>,
+    seen: HashSet<PathBuf>,
+    deps: &'a mut Vec<PathBuf>,
+}
+
+/// A directive line, for errors about it.
+struct Site<'a> {
+    path: &'a Path,
+    source: &'a str,
+    location: Location,
+}
+
+impl Site<'_> {
+    fn error(&self, message: String) -> ShaderError {
+        ShaderError {
+            path: self.path.to_owned(),
+            location: Some(self.location),
+            message,
+            source: self.source.to_owned(),
+        }
+    }
+}
+
+enum Directive<'a> {
+    Include(&'a str),
+    Define(&'a str),
+    Undef(&'a str),
+    Ifdef(&'a str),
+    Ifndef(&'a str),
+    Else,
+    Endif,
+}
+
+/// One open `#ifdef` or `#ifndef`.
+struct Conditional {
+    /// Whether lines are kept right now.
+    active: bool,
+    /// Whether the enclosing region is kept, so `#else` knows whether it can
+    /// switch lines back on.
+    parent_active: bool,
+    seen_else: bool,
+    /// The opening line, for reporting it unclosed.
+    location: Location,
+}
+
+impl<'a> Expander<'a> {
+    fn new(
+        path: &Path,
+        defines: &Defines,
+        constants: &'a Constants,
+        files: &'a mut Vec<PathBuf>,
+        includes_only: bool,
+    ) -> Self {
+        Self {
+            dir: path.parent().unwrap_or(Path::new(".")).to_owned(),
+            expanded: Expanded {
+                text: String::new(),
+                switches: vec![],
+                overrides: vec![],
+                lines: vec![],
+                header_lines: 0,
+                files: vec![],
+            },
+            defines: defines.clone(),
+            constants,
+            includes_only,
+            stack: vec![],
+            seen: HashSet::new(),
+            deps: files,
+        }
+    }
+
+    fn run(mut self, path: &Path) -> Result<Expanded, ShaderError> {
+        self.include(path, None)?;
+        Ok(self.expanded)
+    }
+
+    fn include(&mut self, path: &Path, from: Option<Site>) -> Result<(), ShaderError> {
+        if !self.deps.iter().any(|dep| dep == path) {
+            self.deps.push(path.to_owned());
+        }
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+        if let Some(start) = self.stack.iter().position(|open| *open == key) {
+            let cycle: Vec<_> = self.stack[start..]
+                .iter()
+                .chain([&key])
+                .map(|path| path.display().to_string())
+                .collect();
+            let message = format!("include cycle: {}", cycle.join(" -> "));
+            return Err(match from {
+                Some(site) => site.error(message),
+                None => ShaderError::new(path, message),
+            });
+        }
+        if !self.seen.insert(key.clone()) {
+            return Ok(());
+        }
+
+        let source = std::fs::read_to_string(path).map_err(|err| match &from {
+            Some(site) => site.error(format!("can't include {}: {err}", path.display())),
+            None => ShaderError::new(path, err.to_string()),
+        })?;
+        let file = self.expanded.files.len();
+        self.expanded.files.push((path.to_owned(), source.clone()));
+        self.stack.push(key);
+
+        let mut conditionals: Vec<Conditional> = vec![];
+        let mut file_offset = 0;
+        for (line, raw) in (1..).zip(source.split_inclusive('\n')) {
+            let active = conditionals.last().is_none_or(|cond| cond.active);
+            let site = Site {
+                path,
+                source: &source,
+                location: Location {
+                    line,
+                    column: 1,
+                    offset: file_offset as u32,
+                    length: raw.trim_end().len() as u32,
+                },
+            };
+            file_offset += raw.len();
+
+            if self.includes_only {
+                match parse_include(raw) {
+                    Some(Ok(target)) => {
+                        let target = self.dir.join(target);
+                        self.include(&target, Some(site))?;
+                    }
+                    Some(Err(message)) => return Err(site.error(message)),
+                    None => {
+                        if let Some(Ok(Directive::Ifdef(name) | Directive::Ifndef(name))) =
+                            parse_directive(raw)
+                        {
+                            self.add_switch(name);
+                        }
+                        self.push_line(raw, file, line, site.location.offset as usize);
+                    }
+                }
+                continue;
+            }
+
+            let directive = match parse_directive(raw) {
+                Some(Ok(directive)) => directive,
+                Some(Err(message)) => return Err(site.error(message)),
+                None if !active => continue,
+                None => {
+                    let offset = site.location.offset as usize;
+                    match overrides::specialize_line(raw, self.constants) {
+                        Some(Ok((specialized, name))) => {
+                            self.expanded.overrides.push(name);
+                            self.push_line(&specialized, file, line, offset);
+                        }
+                        Some(Err(message)) => return Err(site.error(message)),
+                        None => self.push_line(raw, file, line, offset),
+                    }
+                    continue;
+                }
+            };
+            match directive {
+                Directive::Ifdef(name) | Directive::Ifndef(name) => {
+                    self.add_switch(name);
+                    let defined = self.defines.contains(name);
+                    let wanted = matches!(directive, Directive::Ifdef(_));
+                    conditionals.push(Conditional {
+                        active: active && defined == wanted,
+                        parent_active: active,
+                        seen_else: false,
+                        location: site.location,
+                    });
+                }
+                Directive::Else => match conditionals.last_mut() {
+                    Some(cond) if !cond.seen_else => {
+                        cond.seen_else = true;
+                        cond.active = cond.parent_active && !cond.active;
+                    }
+                    Some(_) => return Err(site.error("#else after #else".to_owned())),
+                    None => return Err(site.error("#else without #ifdef".to_owned())),
+                },
+                Directive::Endif => {
+                    if conditionals.pop().is_none() {
+                        return Err(site.error("#endif without #ifdef".to_owned()));
+                    }
+                }
+                _ if !active => {}
+                Directive::Include(target) => {
+                    let target = self.dir.join(target);
+                    self.include(&target, Some(site))?;
+                }
+                Directive::Define(name) => {
+                    self.defines.insert(name.to_owned());
+                }
+                Directive::Undef(name) => {
+                    self.defines.remove(name);
+                }
+            }
+        }
+        if let Some(cond) = conditionals.pop() {
+            let site = Site {
+                path,
+                source: &source,
+                location: cond.location,
+            };
+            return Err(site.error("#ifdef without #endif".to_owned()));
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn push_line(&mut self, raw: &str, file: usize, line: u32, file_offset: usize) {
+        self.expanded.lines.push(LineOrigin {
+            offset: self.expanded.text.len(),
+            file,
+            line,
+            file_offset,
+        });
+        self.expanded.text.push_str(raw);
+        if !raw.ends_with('\n') {
+            self.expanded.text.push('\n');
+        }
+    }
+
+    fn add_switch(&mut self, name: &str) {
+        if !self.expanded.switches.iter().any(|switch| switch == name) {
+            self.expanded.switches.push(name.to_owned());
+        }
+    }
+}
+
+/// The directive on `line`, `None` if it isn't one, or an error if it's
+/// malformed.
+fn parse_directive(line: &str) -> Option<Result<Directive<'_>, String>> {
+    let (keyword, argument) = split_directive(line)?;
+    let name = || {
+        let valid = argument.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && argument
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if valid {
+            Ok(argument)
+        } else {
+            Err(format!(
+                "expected a name after #{keyword}, got {argument:?}"
+            ))
+        }
+    };
+    let directive = match keyword {
+        "include" | "import" => include_path(keyword, argument).map(Directive::Include),
+        "define" => name().map(Directive::Define),
+        "undef" => name().map(Directive::Undef),
+        "ifdef" => name().map(Directive::Ifdef),
+        "ifndef" => name().map(Directive::Ifndef),
+        "else" => Ok(Directive::Else),
+        "endif" => Ok(Directive::Endif),
+        _ => return None,
+    };
+    Some(directive)
+}
+
+/// The path of an `#include` or `#import` on `line`, `None` if there isn't
+/// one, or an error if it's malformed.
+fn parse_include(line: &str) -> Option<Result<&str, String>> {
+    let (keyword, argument) = split_directive(line)?;
+    matches!(keyword, "include" | "import").then(|| include_path(keyword, argument))
+}
+
+/// The keyword and argument of a line starting with `#`.
+fn split_directive(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim().strip_prefix('#')?;
+    Some(match rest.split_once(char::is_whitespace) {
+        Some((keyword, argument)) => (keyword, argument.trim()),
+        None => (rest, ""),
+    })
+}
+
+fn include_path<'a>(keyword: &str, argument: &'a str) -> Result<&'a str, String> {
+    argument
+        .strip_prefix('"')
+        .and_then(|path| path.strip_suffix('"'))
+        .filter(|path| !path.is_empty())
+        .ok_or_else(|| format!("expected a quoted path after #{keyword}, got {argument:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn expand_source(source: &str, defines: &[&str]) -> Result<Expanded, ShaderError> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(dir.path(), "shader.wgsl", source);
+        let defines = defines.iter().map(|name| name.to_string()).collect();
+        expand(&path, &defines, &Constants::new(), &mut vec![])
+    }
+
+    /// A shader including `lights.wgsl` and `common.wgsl`, where the lights
+    /// include the common helpers too.
+    fn shader_with_includes(dir: &Path) -> PathBuf {
+        write(dir, "common.wgsl", "fn helper() {}\n");
+        write(
+            dir,
+            "lights.wgsl",
+            "#include \"common.wgsl\"\nfn light() { helper(); }\n",
+        );
+        write(
+            dir,
+            "shader.wgsl",
+            "#include \"lights.wgsl\"\n#import \"common.wgsl\"\nfn main() {}",
+        )
+    }
+
+    #[test]
+    fn includes_are_expanded_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = shader_with_includes(dir.path());
+        let mut files = vec![];
+        let expanded = expand(&path, &Defines::new(), &Constants::new(), &mut files).unwrap();
+        assert_eq!(
+            expanded.text,
+            "fn helper() {}\nfn light() { helper(); }\nfn main() {}\n"
+        );
+        assert_eq!(
+            files,
+            [
+                path.clone(),
+                dir.path().join("lights.wgsl"),
+                dir.path().join("common.wgsl"),
+            ]
+        );
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "b.wgsl", "fn b() {}\n#include \"a.wgsl\"\n");
+        let path = write(dir.path(), "a.wgsl", "#include \"b.wgsl\"\n");
+        let err = expand(&path, &Defines::new(), &Constants::new(), &mut vec![]).unwrap_err();
+        assert_eq!(err.path, dir.path().join("b.wgsl"));
+        assert_eq!(err.location.unwrap().line, 2);
+        assert!(
+            err.message.starts_with("include cycle: "),
+            "{}",
+            err.message
+        );
+        assert!(err.message.ends_with("a.wgsl"), "{}", err.message);
+    }
+
+    #[test]
+    fn a_file_including_itself_is_a_cycle() {
+        let err = expand_source("#include \"shader.wgsl\"\n", &[]).unwrap_err();
+        assert!(
+            err.message.starts_with("include cycle: "),
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn missing_and_malformed_includes_point_at_the_directive() {
+        let err = expand_source("fn a() {}\n#include \"missing.wgsl\"\n", &[]).unwrap_err();
+        assert_eq!(err.location.unwrap().line, 2);
+        assert!(err.message.starts_with("can't include "), "{}", err.message);
+
+        let err = expand_source("#include missing.wgsl\n", &[]).unwrap_err();
+        assert_eq!(err.location.unwrap().line, 1);
+        assert_eq!(
+            err.message,
+            "expected a quoted path after #include, got \"missing.wgsl\""
+        );
+    }
+
+    #[test]
+    fn missing_includes_are_still_listed_as_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(dir.path(), "shader.wgsl", "#include \"later.wgsl\"\n");
+        let mut files = vec![];
+        expand(&path, &Defines::new(), &Constants::new(), &mut files).unwrap_err();
+        assert_eq!(files, [path, dir.path().join("later.wgsl")]);
+    }
+
+    #[test]
+    fn errors_map_back_to_the_included_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = shader_with_includes(dir.path());
+        let mut expanded = expand(&path, &Defines::new(), &Constants::new(), &mut vec![]).unwrap();
+        expanded.wrap("// header\n// header\n", "// footer\n");
+
+        // `light` on the second line of lights.wgsl.
+        let start = expanded.text.find("light").unwrap();
+        let err = ShaderError {
+            path: PathBuf::from("expanded"),
+            location: Some(Location::at(&expanded.text, start..start + 5)),
+            message: "bad light".to_owned(),
+            source: expanded.text.clone(),
+        };
+        let err = expanded.map_error(err);
+
+        let lights = std::fs::read_to_string(dir.path().join("lights.wgsl")).unwrap();
+        let offset = lights.find("light()").unwrap() as u32;
+        assert_eq!(err.path, dir.path().join("lights.wgsl"));
+        assert_eq!(err.source, lights);
+        assert_eq!(
+            err.location,
+            Some(Location {
+                line: 2,
+                column: 4,
+                offset,
+                length: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn errors_in_the_header_stay_put() {
+        let mut expanded = expand_source("fn main() {}\n", &[]).unwrap();
+        expanded.wrap("// header\n", "");
+        let err = ShaderError {
+            path: PathBuf::from("expanded"),
+            location: Some(Location::at(&expanded.text, 3..9)),
+            message: "bad header".to_owned(),
+            source: expanded.text.clone(),
+        };
+        let err = expanded.map_error(err);
+        assert_eq!(err.path, PathBuf::from("expanded"));
+        assert_eq!(err.location.unwrap().line, 1);
+    }
+
+    const PERMUTED: &str = "\
+#ifdef FOG
+fog
+#ifndef CHEAP
+fancy fog
+#endif
+#else
+no fog
+#endif
+#ifdef CHEAP
+cheap
+#endif
+";
+
+    #[test]
+    fn ifdef_and_else_pick_lines_by_defines() {
+        assert_eq!(expand_source(PERMUTED, &[]).unwrap().text, "no fog\n");
+        assert_eq!(
+            expand_source(PERMUTED, &["FOG"]).unwrap().text,
+            "fog\nfancy fog\n"
+        );
+        assert_eq!(
+            expand_source(PERMUTED, &["FOG", "CHEAP"]).unwrap().text,
+            "fog\ncheap\n"
+        );
+    }
+
+    #[test]
+    fn else_inside_a_dropped_region_stays_dropped() {
+        let source = "#ifdef A\n#ifdef B\nb\n#else\nnot b\n#endif\n#endif\n";
+        assert_eq!(expand_source(source, &[]).unwrap().text, "");
+        assert_eq!(expand_source(source, &["A"]).unwrap().text, "not b\n");
+    }
+
+    #[test]
+    fn switches_are_collected_even_in_dropped_regions() {
+        let expanded = expand_source(PERMUTED, &[]).unwrap();
+        assert_eq!(expanded.switches, ["FOG", "CHEAP"]);
+    }
+
+    #[test]
+    fn define_and_undef_change_later_conditionals() {
+        let source = "\
+#define FOG
+#ifdef FOG
+fog
+#endif
+#undef FOG
+#ifdef FOG
+still fog
+#endif
+";
+        assert_eq!(expand_source(source, &[]).unwrap().text, "fog\n");
+    }
+
+    #[test]
+    fn defines_in_dropped_regions_are_ignored() {
+        let source = "#ifdef A\n#define B\n#endif\n#ifdef B\nb\n#endif\n";
+        assert_eq!(expand_source(source, &[]).unwrap().text, "");
+        assert_eq!(expand_source(source, &["A"]).unwrap().text, "b\n");
+    }
+
+    #[test]
+    fn unbalanced_conditionals_are_errors() {
+        let cases = [
+            ("#else\n", 1, "#else without #ifdef"),
+            ("#endif\n", 1, "#endif without #ifdef"),
+            ("#ifdef A\n#else\n#else\n#endif\n", 3, "#else after #else"),
+            ("fn a() {}\n#ifdef A\n", 2, "#ifdef without #endif"),
+            (
+                "#ifdef 1A\n#endif\n",
+                1,
+                "expected a name after #ifdef, got \"1A\"",
+            ),
+        ];
+        for (source, line, message) in cases {
+            let err = expand_source(source, &[]).unwrap_err();
+            assert_eq!(err.message, message, "{source:?}");
+            assert_eq!(err.location.unwrap().line, line, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn conditionals_must_close_in_their_own_file() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "open.wgsl", "#ifdef A\n");
+        let path = write(
+            dir.path(),
+            "shader.wgsl",
+            "#include \"open.wgsl\"\n#endif\n",
+        );
+        let err = expand(&path, &Defines::new(), &Constants::new(), &mut vec![]).unwrap_err();
+        assert_eq!(err.path, dir.path().join("open.wgsl"));
+        assert_eq!(err.message, "#ifdef without #endif");
+    }
+}
@@ -1,13 +1,27 @@
 use crate::app::App;
+use crate::camera::CameraUniform;
+use crate::channels::{ChannelTextures, ChannelsBinding, Sampling};
+use crate::compiler::Compiler;
+use crate::globals::{Clock, Globals, GlobalsBinding};
+use crate::mesh::{Mesh, MeshBuffers, VertexFormat};
+use crate::overrides::{self, Constants, Override};
+use crate::passes::{BufferTargets, Pass, Passes};
+use crate::post::PostChain;
+use crate::preprocess::Defines;
+use crate::shader::{self, ShaderError};
+use crate::storage::{DeclaredBuffers, StorageBinding};
+use crate::uniforms::UniformBlock;
 
 use std::{
     collections::HashMap,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
 use color_eyre::eyre::{eyre, Result};
 use dashmap::DashMap;
-use glam::{vec3, Vec3};
+use image::RgbaImage;
 use wgpu::{util::DeviceExt, RenderPipeline};
 use winit::{
     dpi::PhysicalSize,
@@ -15,37 +29,12 @@ use winit::{
 };
 
 const MSAA_SAMPLES: u32 = 1;
+/// The scene's depth buffer, so meshes seen through the camera hide their
+/// far side.
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 const FORMAT_INDEX: usize = 0;
 const ALPHA_MODES_INDEX: usize = 0;
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: Vec3,
-    // color: [f32; 4],
-}
-
-impl Vertex {
-    fn new(position: Vec3) -> Self {
-        Self { position }
-    }
-}
-
-impl Vertex {
-    const ATTRS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![
-        0 => Float32x3,
-        // 1 => Float32x3,
-    ];
-
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRS,
-        }
-    }
-}
-
 struct ViewportDesc {
     surface: wgpu::Surface,
 }
@@ -54,6 +43,12 @@ struct Viewport {
     desc: ViewportDesc,
     config: wgpu::SurfaceConfiguration,
     render_target: Option<wgpu::Texture>,
+    // The scene is drawn here instead of to the surface when post-processing
+    // is on, with a second texture for intermediate results.
+    scene_texture: wgpu::Texture,
+    scratch_texture: wgpu::Texture,
+    depth_texture: wgpu::Texture,
+    buffers: BufferTargets,
 }
 
 impl ViewportDesc {
@@ -62,36 +57,6 @@ impl ViewportDesc {
         Self { surface }
     }
 
-    fn create_target_texture(
-        &self,
-        device: &wgpu::Device,
-        size: PhysicalSize<u32>,
-        format: wgpu::TextureFormat,
-    ) -> Option<wgpu::Texture> {
-        if MSAA_SAMPLES == 1 {
-            return None;
-        }
-        let limits = wgpu::Limits::default();
-        let max_dim = limits.max_texture_dimension_3d;
-        if size.width > max_dim || size.height > max_dim {
-            return None;
-        }
-        Some(device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("render target"),
-            size: wgpu::Extent3d {
-                width: size.width.min(limits.max_texture_dimension_3d),
-                height: size.height.min(limits.max_texture_dimension_3d),
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: MSAA_SAMPLES,
-            dimension: wgpu::TextureDimension::D2,
-            format,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
-        }))
-    }
-
     fn build(
         self,
         adapter: &wgpu::Adapter,
@@ -109,7 +74,10 @@ impl ViewportDesc {
             alpha_mode: caps.alpha_modes[ALPHA_MODES_INDEX],
             view_formats: vec![],
         };
-        let render_target = self.create_target_texture(device, size, format);
+        let render_target = create_target_texture(device, size, format);
+        let scene_texture = create_offscreen_texture(device, size, format, "scene texture");
+        let scratch_texture = create_offscreen_texture(device, size, format, "scratch texture");
+        let depth_texture = create_depth_texture(device, size);
 
         self.surface.configure(device, &config);
 
@@ -117,6 +85,10 @@ impl ViewportDesc {
             desc: self,
             config,
             render_target,
+            scene_texture,
+            scratch_texture,
+            depth_texture,
+            buffers: BufferTargets::default(),
         }
     }
 }
@@ -129,13 +101,19 @@ impl Viewport {
         self.config.width = size.width;
         self.config.height = size.height;
         self.desc.surface.configure(device, &self.config);
-        let next_target = self
-            .desc
-            .create_target_texture(device, size, self.config.format);
+        let next_target = create_target_texture(device, size, self.config.format);
         let old = std::mem::replace(&mut self.render_target, next_target);
         if let Some(old) = old {
             old.destroy();
         }
+        let format = self.config.format;
+        let next_scene = create_offscreen_texture(device, size, format, "scene texture");
+        std::mem::replace(&mut self.scene_texture, next_scene).destroy();
+        let next_scratch = create_offscreen_texture(device, size, format, "scratch texture");
+        std::mem::replace(&mut self.scratch_texture, next_scratch).destroy();
+        let next_depth = create_depth_texture(device, size);
+        std::mem::replace(&mut self.depth_texture, next_depth).destroy();
+        self.buffers.resize(device, size);
     }
 
     fn get_current_texture(&mut self) -> wgpu::SurfaceTexture {
@@ -146,22 +124,156 @@ impl Viewport {
     }
 }
 
+fn create_target_texture(
+    device: &wgpu::Device,
+    size: PhysicalSize<u32>,
+    format: wgpu::TextureFormat,
+) -> Option<wgpu::Texture> {
+    if MSAA_SAMPLES == 1 {
+        return None;
+    }
+    let limits = wgpu::Limits::default();
+    let max_dim = limits.max_texture_dimension_3d;
+    if size.width > max_dim || size.height > max_dim {
+        return None;
+    }
+    Some(device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("render target"),
+        size: wgpu::Extent3d {
+            width: size.width.min(limits.max_texture_dimension_3d),
+            height: size.height.min(limits.max_texture_dimension_3d),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: MSAA_SAMPLES,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    }))
+}
+
+fn create_offscreen_texture(
+    device: &wgpu::Device,
+    size: PhysicalSize<u32>,
+    format: wgpu::TextureFormat,
+    label: &str,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size.width.max(1),
+            height: size.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
+fn create_depth_texture(device: &wgpu::Device, size: PhysicalSize<u32>) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("depth texture"),
+        size: wgpu::Extent3d {
+            width: size.width.max(1),
+            height: size.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: MSAA_SAMPLES,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+/// The scene pipeline for one permutation of the shader, or a buffer's.
+pub struct ScenePipeline {
+    pipeline: RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    // Layout and default values of the shader's uniform.
+    uniforms: UniformBlock,
+    /// The names the shader's `#ifdef`s test.
+    switches: Vec<String>,
+    /// The shader's `override`s, with the values this pipeline was built with.
+    overrides: Vec<Override>,
+    /// Drawn over the whole target instead of the mesh, for shaders without a
+    /// vertex stage.
+    fullscreen: bool,
+}
+
+impl ScenePipeline {
+    /// Draw with this pipeline's bind groups already set.
+    fn draw<'a>(&self, render_pass: &mut wgpu::RenderPass<'a>, mesh: &'a MeshBuffers) {
+        if self.fullscreen {
+            render_pass.draw(0..3, 0..1);
+        } else {
+            mesh.draw(render_pass);
+        }
+    }
+}
+
+/// A background build of one permutation.
+struct SceneBuild {
+    constants: Constants,
+    /// Everything the build read, whether or not it succeeded.
+    files: Vec<PathBuf>,
+    scene: Result<ScenePipeline, ShaderError>,
+}
+
 pub struct Renderer {
-    device: wgpu::Device,
+    shader_path: PathBuf,
+    /// `shader_path` and everything any built permutation includes.
+    shader_files: Vec<PathBuf>,
+    device: Arc<wgpu::Device>,
     queue: wgpu::Queue,
     viewports: HashMap<WindowId, Viewport>,
-    render_pipeline: RenderPipeline,
-    bind_group_layout: wgpu::BindGroupLayout,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
+    format: wgpu::TextureFormat,
+    /// Every permutation built since the shader last changed, so switching
+    /// back to one doesn't recompile it. Each keeps the override values it
+    /// was last built with.
+    scene_pipelines: HashMap<Defines, ScenePipeline>,
+    /// The permutation being drawn: the one asked for, unless that failed.
+    defines: Defines,
+    // Permutations and override values that failed since the last reload. They
+    // aren't retried every frame.
+    failed: Vec<(Defines, Constants)>,
+    /// Scene pipelines are built off the render thread and swapped in when
+    /// they're ready; until then the previous one keeps drawing.
+    compiler: Compiler<Defines, SceneBuild>,
+    /// The override values of each permutation that's building.
+    building: HashMap<Defines, Constants>,
+    /// Block on background builds instead, so a headless render always shows
+    /// the permutation it asked for.
+    wait_for_builds: bool,
+    /// What scene and buffer shaders with a vertex stage draw.
+    mesh: MeshBuffers,
+    post: PostChain,
+    passes: Passes,
+    /// The buffers' textures for headless renders, which have no viewport.
+    headless_buffers: BufferTargets,
+    globals: GlobalsBinding,
+    channels: ChannelsBinding,
+    /// The files picked for the scene's channels.
+    channel_textures: ChannelTextures,
+    storage: StorageBinding,
+    clock: Clock,
     egui_renderers: HashMap<WindowId, egui_wgpu::Renderer>,
     // pub egui_contexts: HashMap<WindowId, egui::Context>,
     egui_contexts: Arc<DashMap<WindowId, egui::Context>>,
 }
 
 impl Renderer {
+    /// Draws `shader_path`, and the mesh at `mesh_path` if its shader has a
+    /// vertex stage, or a triangle without one.
     pub async fn new(
+        shader_path: PathBuf,
+        mesh_path: Option<&Path>,
         viewports: &mut [(&Window, wgpu::Color)],
         egui_contexts: Arc<DashMap<WindowId, egui::Context>>,
     ) -> Result<Self> {
@@ -179,17 +291,7 @@ impl Renderer {
             .await
             .ok_or_else(|| eyre!("Failed to find an appropriate adapter"))?;
 
-        // Create the logical device and command queue
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::downlevel_defaults(),
-                },
-                None,
-            )
-            .await?;
+        let (device, queue) = request_device(&adapter).await?;
 
         let viewport_map: HashMap<WindowId, Viewport> = viewports
             .iter()
@@ -220,18 +322,107 @@ impl Renderer {
             })
             .collect();
 
-        let (render_pipeline, bind_group_layout, vertex_buffer, index_buffer, num_indices) =
-            Self::create_pipeline_and_buffers(&device, &primary_viewport.config.format)?;
+        let format = primary_viewport.config.format;
+        Self::from_device(
+            shader_path,
+            mesh_path,
+            (device, queue),
+            format,
+            viewport_map,
+            egui_renderers,
+            egui_contexts,
+        )
+    }
+
+    /// A renderer without a window or surface that renders into textures of
+    /// `format` and reads them back. Falls back to a software adapter when
+    /// there's no GPU, or always with `force_fallback_adapter`.
+    pub async fn new_headless(
+        shader_path: PathBuf,
+        mesh_path: Option<&Path>,
+        format: wgpu::TextureFormat,
+        force_fallback_adapter: bool,
+    ) -> Result<Self> {
+        if bgra_or_rgba(format).is_none() {
+            return Err(eyre!("can't read back {format:?} textures"));
+        }
+        let device = request_headless_device(force_fallback_adapter).await?;
+        let mut renderer = Self::from_device(
+            shader_path,
+            mesh_path,
+            device,
+            format,
+            HashMap::new(),
+            HashMap::new(),
+            Arc::new(DashMap::new()),
+        )?;
+        renderer.wait_for_builds = true;
+        Ok(renderer)
+    }
+
+    fn from_device(
+        shader_path: PathBuf,
+        mesh_path: Option<&Path>,
+        (device, queue): (wgpu::Device, wgpu::Queue),
+        format: wgpu::TextureFormat,
+        viewports: HashMap<WindowId, Viewport>,
+        egui_renderers: HashMap<WindowId, egui_wgpu::Renderer>,
+        egui_contexts: Arc<DashMap<WindowId, egui::Context>>,
+    ) -> Result<Self> {
+        let device = Arc::new(device);
+        let globals = GlobalsBinding::new(&device);
+        let channels = ChannelsBinding::new(&device, &queue);
+        let storage = StorageBinding::new(&device);
+        let mesh = match mesh_path {
+            Some(path) => Mesh::load(path)?,
+            None => Mesh::triangle(),
+        };
+        let mesh = MeshBuffers::new(&device, &mesh);
+        let shared = [&globals.layout, &channels.layout];
+        let render_layouts = [shared[0], shared[1], &storage.render_layout].map(Arc::clone);
+        let compute_layouts = [shared[0], shared[1], &storage.compute_layout].map(Arc::clone);
+        let passes = Passes::new(&shader_path, render_layouts, compute_layouts, mesh.format)?;
+        let mut shader_files = vec![];
+        let defines = Defines::new();
+        let setup = PipelineSetup {
+            color_target: scene_target(format),
+            shared_layouts: &[&globals.layout, &channels.layout, &storage.render_layout],
+            vertex_format: mesh.format,
+            depth: true,
+            storage: &passes.declared_buffers(),
+        };
+        let scene = create_pipeline(
+            &device,
+            setup,
+            &shader_path,
+            &defines,
+            &Constants::new(),
+            &mut shader_files,
+        )?;
+        let post = PostChain::new(&device, format, &globals.layout);
 
         Ok(Self {
+            shader_path,
+            shader_files,
             device,
             queue,
-            viewports: viewport_map,
-            render_pipeline,
-            bind_group_layout,
-            vertex_buffer,
-            index_buffer,
-            num_indices,
+            viewports,
+            format,
+            scene_pipelines: HashMap::from([(defines.clone(), scene)]),
+            defines,
+            failed: vec![],
+            compiler: Compiler::new(),
+            building: HashMap::new(),
+            wait_for_builds: false,
+            mesh,
+            post,
+            passes,
+            headless_buffers: BufferTargets::default(),
+            globals,
+            channels,
+            channel_textures: ChannelTextures::default(),
+            storage,
+            clock: Clock::new(),
             // platform,
             egui_renderers,
             egui_contexts,
@@ -239,20 +430,122 @@ impl Renderer {
     }
     // async fn run(event_loop: EventLoop<()>, viewports: Vec<(Window, wgpu::Color)>) {
 
-    pub fn reload(&mut self) -> Result<()> {
-        let (_, viewport) = self
-            .viewports
-            .iter()
-            .next()
-            .ok_or_else(|| eyre!("failed to get viewport"))?;
-        let (render_pipeline, bind_group_layout, vertex_buffer, index_buffer, num_indices) =
-            Self::create_pipeline_and_buffers(&self.device, &viewport.config.format)?;
-        self.render_pipeline = render_pipeline;
-        self.bind_group_layout = bind_group_layout;
-        self.vertex_buffer = vertex_buffer;
-        self.index_buffer = index_buffer;
-        self.num_indices = num_indices;
-        Ok(())
+    /// Start rebuilding the pipelines that use any of the `changed` shader
+    /// files, directly or through an include. The new pipelines are swapped in
+    /// when they're ready. A shader that doesn't compile leaves its current
+    /// pipeline in place to keep rendering.
+    pub fn reload(&mut self, changed: &[PathBuf]) {
+        self.post.reload(&self.device, changed);
+        self.passes.reload(&self.device, changed);
+        self.channel_textures.reload(changed);
+        if shader::depends_on(&self.shader_files, changed) {
+            // Every other permutation is out of date now. They're rebuilt when
+            // they're next picked.
+            self.compiler.cancel_all();
+            self.building.clear();
+            self.failed.clear();
+            self.scene_pipelines
+                .retain(|defines, _| *defines == self.defines);
+            let constants = constants(&self.scene().overrides);
+            self.build(self.defines.clone(), constants);
+        }
+    }
+
+    /// Draw the permutation for `defines` with `overrides` from now on if
+    /// it's been built with those override values, and start building it if
+    /// not. Until it's ready, or if it fails, the current one keeps drawing.
+    fn select(&mut self, defines: &Defines, overrides: &[Override]) {
+        let constants = constants(overrides);
+        let built = self.scene_pipelines.get(defines).is_some_and(|scene| {
+            scene
+                .overrides
+                .iter()
+                .all(|built| constants.get(&built.name).is_none_or(|v| *v == built.value))
+        });
+        if built {
+            self.defines = defines.clone();
+            return;
+        }
+        let key = (defines.clone(), constants);
+        if self.failed.contains(&key) || self.building.get(defines) == Some(&key.1) {
+            return;
+        }
+        self.build(key.0, key.1);
+    }
+
+    fn build(&mut self, defines: Defines, constants: Constants) {
+        let device = Arc::clone(&self.device);
+        let globals_layout = Arc::clone(&self.globals.layout);
+        let channels_layout = Arc::clone(&self.channels.layout);
+        let storage_layout = Arc::clone(&self.storage.render_layout);
+        let format = self.format;
+        let vertex_format = self.mesh.format;
+        let shader_path = self.shader_path.clone();
+        let storage = self.passes.declared_buffers();
+        self.building.insert(defines.clone(), constants.clone());
+        self.compiler.spawn(defines.clone(), move || {
+            let mut files = vec![];
+            let setup = PipelineSetup {
+                color_target: scene_target(format),
+                shared_layouts: &[&globals_layout, &channels_layout, &storage_layout],
+                vertex_format,
+                depth: true,
+                storage: &storage,
+            };
+            let scene = shader::catch_panic(&shader_path, || {
+                create_pipeline(
+                    &device,
+                    setup,
+                    &shader_path,
+                    &defines,
+                    &constants,
+                    &mut files,
+                )
+            });
+            SceneBuild {
+                constants,
+                files,
+                scene,
+            }
+        });
+    }
+
+    /// Swap in the scene pipelines that finished building. The app's error
+    /// goes away once the permutation it asks for builds. Returns the first
+    /// failure.
+    fn finish_builds(&mut self, app: &mut App, wait: bool) -> Result<(), ShaderError> {
+        let mut result = Ok(());
+        for (defines, build) in self.compiler.finished(wait) {
+            self.building.remove(&defines);
+            for file in build.files {
+                if !self.shader_files.contains(&file) {
+                    self.shader_files.push(file);
+                }
+            }
+            match build.scene {
+                Ok(scene) => {
+                    if defines == app.defines {
+                        app.shader_error = None;
+                    }
+                    self.scene_pipelines.insert(defines, scene);
+                }
+                Err(err) => {
+                    self.failed.push((defines, build.constants));
+                    result = result.and(Err(err));
+                }
+            }
+        }
+        result
+    }
+
+    fn scene(&self) -> &ScenePipeline {
+        &self.scene_pipelines[&self.defines]
+    }
+
+    /// Replace the frame clock, e.g. with a fixed one for deterministic
+    /// renders.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
     }
 
     pub fn resize(&mut self, window: &Window, size: PhysicalSize<u32>) {
@@ -274,6 +567,11 @@ impl Renderer {
             return;
         }
 
+        if let Err(err) = self.prepare_frame(app, size) {
+            eprintln!("{err}");
+            app.shader_error = Some(err);
+        }
+
         // Render the frame.
         if let Some(viewport) = self.viewports.get_mut(&window.id()) {
             let frame = viewport.get_current_texture();
@@ -285,25 +583,260 @@ impl Renderer {
             let target_view = target
                 .as_ref()
                 .map(|x| x.create_view(&wgpu::TextureViewDescriptor::default()));
+            let scene_view = viewport
+                .scene_texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            let scratch_view = viewport
+                .scratch_texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            let depth_view = viewport
+                .depth_texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
             // let target_view = match target {
             //     Some(x) => Some(x.create_view(&wgpu::TextureViewDescriptor::default())),
             //     None => None,
             // };
+            let mut buffers = std::mem::take(&mut viewport.buffers);
+            buffers.update(&self.device, &self.passes.graph, size);
 
-            let encoder = self
-                .device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("Render Encoder"),
-                });
-
-            self.render_background(app, encoder, &view, target_view.as_ref());
+            self.render_scene(
+                app,
+                &view,
+                target_view.as_ref(),
+                [&scene_view, &scratch_view],
+                &depth_view,
+                &mut buffers,
+            );
             // frame.present();
+            if let Some(viewport) = self.viewports.get_mut(&window.id()) {
+                viewport.buffers = buffers;
+            }
 
             self.render_ui(window, egui_state, app, view);
             frame.present();
         }
     }
 
+    /// Render one frame of `app` into a texture of the given size and read it
+    /// back. Post shaders that fail to compile are an error here rather than
+    /// being skipped.
+    pub fn render_to_image(&mut self, app: &mut App, size: PhysicalSize<u32>) -> Result<RgbaImage> {
+        if size.width == 0 || size.height == 0 {
+            return Err(eyre!("can't render an empty image"));
+        }
+        self.prepare_frame(app, size)?;
+
+        let output = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("output texture"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let target = create_target_texture(&self.device, size, self.format);
+        let scene = create_offscreen_texture(&self.device, size, self.format, "scene texture");
+        let scratch = create_offscreen_texture(&self.device, size, self.format, "scratch texture");
+        let depth = create_depth_texture(&self.device, size);
+
+        // Buffers carry over between renders, so an animation's feedback
+        // builds up frame to frame.
+        let mut buffers = std::mem::take(&mut self.headless_buffers);
+        buffers.update(&self.device, &self.passes.graph, size);
+
+        let view = |texture: &wgpu::Texture| texture.create_view(&Default::default());
+        self.render_scene(
+            app,
+            &view(&output),
+            target.as_ref().map(view).as_ref(),
+            [&view(&scene), &view(&scratch)],
+            &view(&depth),
+            &mut buffers,
+        );
+        self.headless_buffers = buffers;
+        self.read_texture(&output, size)
+    }
+
+    pub fn render_to_png(
+        &mut self,
+        app: &mut App,
+        size: PhysicalSize<u32>,
+        path: &Path,
+    ) -> Result<()> {
+        self.render_to_image(app, size)?.save(path)?;
+        Ok(())
+    }
+
+    /// Advance the clock and get everything the frame depends on up to date.
+    fn prepare_frame(&mut self, app: &mut App, size: PhysicalSize<u32>) -> Result<(), ShaderError> {
+        self.clock.tick();
+        app.camera_controls
+            .update(&mut app.camera, self.clock.delta_time);
+        let resolution = [size.width as f32, size.height as f32];
+        let globals = Globals::new(&self.clock, &app.mouse, resolution);
+        let camera = CameraUniform::new(&app.camera, resolution);
+        self.globals.write(&self.queue, &globals, &camera);
+
+        let mut scene_result = self.finish_builds(app, false);
+        self.select(&app.defines, &app.overrides);
+        if self.wait_for_builds {
+            scene_result = scene_result.and(self.finish_builds(app, true));
+            self.select(&app.defines, &app.overrides);
+        }
+
+        // Controls follow the shader's uniform, keeping values for members
+        // that survived the reload.
+        let scene = self.scene();
+        if !app.uniforms.layout_matches(&scene.uniforms) {
+            app.uniforms = scene.uniforms.with_values_from(&app.uniforms);
+        }
+        if app.switches != scene.switches {
+            app.switches.clone_from(&scene.switches);
+        }
+        if !overrides::same_layout(&app.overrides, &scene.overrides) {
+            app.overrides = overrides::carry_over(&scene.overrides, &app.overrides);
+        }
+
+        let passes_result = self.passes.prepare(&self.device, self.wait_for_builds);
+        let channels_result =
+            self.channel_textures
+                .update(&self.device, &self.queue, &app.channels, self.clock.time);
+        let graph_channels = self.passes.graph.channels.iter();
+        app.graph_channels = graph_channels.map(|name| name.get_ref().clone()).collect();
+        let post_result =
+            self.post
+                .prepare(&self.device, &mut app.post_passes, self.wait_for_builds);
+        app.compiling =
+            self.compiler.is_busy() || self.passes.is_compiling() || self.post.is_compiling();
+        scene_result
+            .and(passes_result)
+            .and(channels_result)
+            .and(post_result)
+    }
+
+    /// Run the compute passes, draw the buffers, then the scene, and run the
+    /// post chain, ending up in `view`.
+    fn render_scene(
+        &mut self,
+        app: &mut App,
+        view: &wgpu::TextureView,
+        target_view: Option<&wgpu::TextureView>,
+        textures: [&wgpu::TextureView; 2],
+        depth: &wgpu::TextureView,
+        buffers: &mut BufferTargets,
+    ) {
+        self.run_compute(buffers);
+        self.render_buffers(buffers);
+        let graph = &self.passes.graph;
+        // The graph's channels come first, files go on the rest.
+        let channel_views = buffers.channel_views(graph, &graph.channels, Pass::Scene);
+        let scene_channels: Vec<_> = app
+            .channels
+            .iter()
+            .enumerate()
+            .map(|(i, input)| match channel_views.get(i) {
+                Some(view) => Some((view, Sampling::default())),
+                None => Some((self.channel_textures.view(i)?, input.sampling)),
+            })
+            .collect();
+        let channels = self.channels.bind_group(&self.device, &scene_channels);
+        let storage = self
+            .storage
+            .render_bind_group(&self.device, &buffers.storage_buffers(graph));
+        buffers.swap();
+
+        let encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+
+        // With post-processing on, the scene goes offscreen first and the
+        // last pass writes the result to `view`.
+        let post = self.post.is_active(&app.post_passes);
+        let scene_output = if post { textures[0] } else { view };
+        self.render_background(
+            app,
+            encoder,
+            scene_output,
+            target_view,
+            depth,
+            [&channels, &storage],
+        );
+        if post {
+            self.post.render(
+                &self.device,
+                &self.queue,
+                &app.post_passes,
+                &self.globals.bind_group,
+                textures,
+                view,
+            );
+        }
+    }
+
+    fn read_texture(&self, texture: &wgpu::Texture, size: PhysicalSize<u32>) -> Result<RgbaImage> {
+        let unpadded_row = size.width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row = unpadded_row.div_ceil(align) * align;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_row * size.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let mut pixels = Vec::with_capacity((unpadded_row * size.height) as usize);
+        for row in slice.get_mapped_range().chunks(padded_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_row as usize]);
+        }
+        buffer.unmap();
+        if bgra_or_rgba(self.format) == Some(ChannelOrder::Bgra) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        RgbaImage::from_raw(size.width, size.height, pixels)
+            .ok_or_else(|| eyre!("readback buffer is the wrong size"))
+    }
+
     fn render_ui(
         &mut self,
         window: &Window,
@@ -377,18 +910,118 @@ impl Renderer {
         );
     }
 
+    /// Dispatch every compute pass whose pipeline is ready.
+    fn run_compute(&self, buffers: &BufferTargets) {
+        let graph = &self.passes.graph;
+        if graph.compute.is_empty() {
+            return;
+        }
+        let storage_textures = buffers.storage_textures(graph);
+        let storage = self.storage.compute_bind_group(
+            &self.device,
+            &buffers.storage_buffers(graph),
+            &storage_textures.iter().collect::<Vec<_>>(),
+        );
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Compute Encoder"),
+            });
+        for compute in &graph.compute {
+            let Some(pipeline) = self.passes.compute_pipeline(compute) else {
+                continue;
+            };
+            let bind_group = create_bind_group(
+                &self.device,
+                &pipeline.bind_group_layout,
+                &pipeline.uniforms.to_bytes(),
+            );
+            let channel_views = buffers.channel_views(graph, &compute.channels, Pass::Compute);
+            let channels = self
+                .channels
+                .bind_group(&self.device, &buffer_channels(&channel_views));
+            let [x, y, z] = pipeline.workgroups(compute.threads(buffers.size()));
+
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass"),
+            });
+            compute_pass.set_pipeline(&pipeline.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.set_bind_group(1, &self.globals.bind_group, &[]);
+            compute_pass.set_bind_group(2, &channels, &[]);
+            compute_pass.set_bind_group(3, &storage, &[]);
+            compute_pass.dispatch_workgroups(x, y, z);
+        }
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    /// Draw every buffer whose pipeline is ready into this frame's texture.
+    fn render_buffers(&self, buffers: &BufferTargets) {
+        let graph = &self.passes.graph;
+        if graph.buffers.is_empty() {
+            return;
+        }
+        let storage = self
+            .storage
+            .render_bind_group(&self.device, &buffers.storage_buffers(graph));
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Buffer Encoder"),
+            });
+        for (index, buffer) in graph.buffers.iter().enumerate() {
+            let Some(pipeline) = self.passes.buffer_pipeline(buffer) else {
+                continue;
+            };
+            let bind_group = create_bind_group(
+                &self.device,
+                &pipeline.bind_group_layout,
+                &pipeline.uniforms.to_bytes(),
+            );
+            let channel_views = buffers.channel_views(graph, &buffer.channels, Pass::Buffer(index));
+            let channels = self
+                .channels
+                .bind_group(&self.device, &buffer_channels(&channel_views));
+            let target = buffers.target(buffer.name.get_ref());
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(buffer.name.get_ref()),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&pipeline.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.set_bind_group(1, &self.globals.bind_group, &[]);
+            render_pass.set_bind_group(2, &channels, &[]);
+            render_pass.set_bind_group(3, &storage, &[]);
+            pipeline.draw(&mut render_pass, &self.mesh);
+        }
+        self.queue.submit(Some(encoder.finish()));
+    }
+
     fn render_background(
         &mut self,
         app: &mut App,
         mut encoder: wgpu::CommandEncoder,
         view: &wgpu::TextureView,
         target_view: Option<&wgpu::TextureView>,
+        depth: &wgpu::TextureView,
+        // The channels and storage, at groups 2 and 3.
+        [channels, storage]: [&wgpu::BindGroup; 2],
     ) {
         // Render the triangle.
+        let scene = self.scene();
         let bind_group = create_bind_group(
             &self.device,
-            &self.bind_group_layout,
-            &app.triangle_color.into(),
+            &scene.bind_group_layout,
+            &app.uniforms.to_bytes(),
         );
         {
             let (view, resolve_target) = match target_view {
@@ -405,134 +1038,307 @@ impl Renderer {
                         store: true,
                     },
                 })],
-                depth_stencil_attachment: None,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
             });
-            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_pipeline(&scene.pipeline);
             render_pass.set_bind_group(0, &bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+            render_pass.set_bind_group(1, &self.globals.bind_group, &[]);
+            render_pass.set_bind_group(2, channels, &[]);
+            render_pass.set_bind_group(3, storage, &[]);
+            scene.draw(&mut render_pass, &self.mesh);
         }
         self.queue.submit(Some(encoder.finish()));
     }
+}
 
-    fn create_pipeline_and_buffers(
-        device: &wgpu::Device,
-        swapchain_format: &wgpu::TextureFormat,
-    ) -> Result<(
-        RenderPipeline,
-        wgpu::BindGroupLayout,
-        wgpu::Buffer,
-        wgpu::Buffer,
-        u32,
-    )> {
-        // Create shaders.
-        let shader = std::fs::read_to_string("src/shaders/triangle.wgsl")?;
-        let vertex_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Vertex Shader"),
-            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(&shader)),
-        });
-
-        let fragment_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Fragment Shader"),
-            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(&shader)),
-        });
-
-        // Create vertex and index buffers.
-        let vertices: &[Vertex] = &[
-            Vertex::new(vec3(-0.5, -0.5, 0.0)),
-            Vertex::new(vec3(0.5, -0.5, 0.0)),
-            Vertex::new(vec3(0.0, 0.5, 0.0)),
-        ];
-        let indices: &[u16] = &[0, 1, 2];
-
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+/// What a scene or buffer pipeline is built against, besides its shader.
+pub struct PipelineSetup<'a> {
+    pub color_target: wgpu::ColorTargetState,
+    /// Group 1 on, the same for every pipeline.
+    pub shared_layouts: &'a [&'a wgpu::BindGroupLayout],
+    /// The vertices of the mesh, drawn if the shader has a vertex stage.
+    pub vertex_format: VertexFormat,
+    /// Whether the pass has a `DEPTH_FORMAT` depth buffer to test against.
+    pub depth: bool,
+    /// What's bound to the storage buffer slots.
+    pub storage: &'a DeclaredBuffers,
+}
 
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(indices),
-            usage: wgpu::BufferUsages::INDEX,
-        });
+/// Build a pipeline that draws `shader_path` into `setup.color_target`.
+pub fn create_pipeline(
+    device: &wgpu::Device,
+    setup: PipelineSetup,
+    shader_path: &Path,
+    defines: &Defines,
+    constants: &Constants,
+    shader_files: &mut Vec<PathBuf>,
+) -> Result<ScenePipeline, ShaderError> {
+    let mut defines = defines.clone();
+    if setup.color_target.format.describe().srgb {
+        defines.insert(SRGB_TARGET.to_owned());
+    }
+    // Create shaders. Validate up front, wgpu panics on invalid modules.
+    let loaded = shader::load_shader(shader_path, &defines, constants, shader_files)?;
+    let uniforms = UniformBlock::reflect(&loaded.module, &loaded.source);
+    let (vertex, fragment) = loaded.stages(shader_path)?;
+    let fullscreen = loaded.draws_fullscreen();
+    let PipelineSetup {
+        color_target,
+        shared_layouts,
+        vertex_format,
+        depth,
+        storage,
+    } = setup;
+    storage.check(shader_path, &[&vertex, &fragment], shader_files)?;
+    let attributes = vertex_format.attributes();
+    if !fullscreen {
+        // wgpu only says the pipeline is invalid, so say which input is missing.
+        for location in vertex.input_locations() {
+            if !attributes
+                .iter()
+                .any(|attr| attr.shader_location == location)
+            {
+                let message = format!(
+                    "the vertex stage reads @location({location}), which the mesh doesn't \
+                     have. Meshes have positions at 0, and normals at 1 and UVs at 2 if the \
+                     file has them"
+                );
+                return Err(ShaderError::new(shader_path, message));
+            }
+        }
+    }
+    let vertex_layout = wgpu::VertexBufferLayout {
+        array_stride: vertex_format.stride(),
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &attributes,
+    };
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Color Bind Group Layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: wgpu::BufferSize::new(16),
-                },
-                count: None,
-            }],
-        });
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Uniform Bind Group Layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(uniforms.buffer_size() as u64),
+            },
+            count: None,
+        }],
+    });
 
-        // Create render pipeline.
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges: &[],
-            });
+    // Create render pipeline.
+    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Render Pipeline Layout"),
+        bind_group_layouts: &[&[&bind_group_layout], shared_layouts].concat(),
+        push_constant_ranges: &[],
+    });
 
-        let mut color_target = wgpu::ColorTargetState::from(*swapchain_format);
-        color_target.blend = Some(wgpu::BlendState::ALPHA_BLENDING);
+    // Things naga can't catch on its own, like a missing entry point, are
+    // reported by wgpu when the pipeline is built.
+    let render_pipeline = shader::capture_errors(device, shader_path, || {
+        let vertex_shader = vertex.create_module(device, "Vertex Shader");
+        let fragment_shader = fragment.create_module(device, "Fragment Shader");
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Render Pipeline"),
             layout: Some(&render_pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &vertex_shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
+                entry_point: &vertex.entry_point,
+                buffers: if fullscreen {
+                    &[]
+                } else {
+                    std::slice::from_ref(&vertex_layout)
+                },
             },
             fragment: Some(wgpu::FragmentState {
                 module: &fragment_shader,
-                entry_point: "fs_main",
+                entry_point: &fragment.entry_point,
                 targets: &[Some(color_target)],
             }),
             primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
+            depth_stencil: depth.then(|| wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
             multisample: wgpu::MultisampleState {
                 count: MSAA_SAMPLES,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
             multiview: None,
-        });
+        })
+    })?;
+
+    Ok(ScenePipeline {
+        pipeline: render_pipeline,
+        bind_group_layout,
+        uniforms,
+        switches: loaded.switches,
+        overrides: loaded.overrides,
+        fullscreen,
+    })
+}
+
+/// Defined for shaders whose color target is sRGB, i.e. encodes the linear
+/// colors written to it.
+pub const SRGB_TARGET: &str = "SRGB_TARGET";
+
+/// The scene blends over the background color.
+fn scene_target(format: wgpu::TextureFormat) -> wgpu::ColorTargetState {
+    wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+        write_mask: wgpu::ColorWrites::ALL,
+    }
+}
+
+/// A device that doesn't need a surface. Falls back to a software adapter
+/// when there's no GPU, or always with `force_fallback_adapter`.
+pub async fn request_headless_device(
+    force_fallback_adapter: bool,
+) -> Result<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::default();
+    let request = |force_fallback_adapter| {
+        instance.request_adapter(&wgpu::RequestAdapterOptions {
+            force_fallback_adapter,
+            ..Default::default()
+        })
+    };
+    let adapter = match request(force_fallback_adapter).await {
+        Some(adapter) => adapter,
+        None => request(true)
+            .await
+            .ok_or_else(|| eyre!("Failed to find an appropriate adapter"))?,
+    };
+    request_device(&adapter).await
+}
+
+async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
+    // Create the logical device and command queue
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::downlevel_defaults(),
+            },
+            None,
+        )
+        .await?;
+    Ok((device, queue))
+}
 
-        Ok((
-            render_pipeline,
-            bind_group_layout,
-            vertex_buffer,
-            index_buffer,
-            indices.len() as u32,
-        ))
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChannelOrder {
+    Rgba,
+    Bgra,
+}
+
+/// The 8-bit formats that can be turned into an RGBA image on readback.
+fn bgra_or_rgba(format: wgpu::TextureFormat) -> Option<ChannelOrder> {
+    use wgpu::TextureFormat::*;
+    match format {
+        Rgba8Unorm | Rgba8UnormSrgb => Some(ChannelOrder::Rgba),
+        Bgra8Unorm | Bgra8UnormSrgb => Some(ChannelOrder::Bgra),
+        _ => None,
     }
 }
 
 fn create_bind_group(
     device: &wgpu::Device,
     layout: &wgpu::BindGroupLayout,
-    color: &[f32; 4],
+    uniforms: &[u8],
 ) -> wgpu::BindGroup {
-    let color_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Color Buffer"),
-        contents: bytemuck::cast_slice(color),
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Uniform Buffer"),
+        contents: uniforms,
         usage: wgpu::BufferUsages::UNIFORM,
     });
 
     device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("Color Bind Group"),
+        label: Some("Uniform Bind Group"),
         layout,
         entries: &[wgpu::BindGroupEntry {
             binding: 0,
-            resource: color_buffer.as_entire_binding(),
+            resource: uniform_buffer.as_entire_binding(),
         }],
     })
 }
+
+/// Buffers and storage textures on channels, sampled the default way.
+fn buffer_channels(views: &[wgpu::TextureView]) -> Vec<Option<(&wgpu::TextureView, Sampling)>> {
+    views
+        .iter()
+        .map(|view| Some((view, Sampling::default())))
+        .collect()
+}
+
+fn constants(overrides: &[Override]) -> Constants {
+    overrides
+        .iter()
+        .map(|o| (o.name.clone(), o.value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertex_shaders_can_read_storage_buffers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scene.wgsl");
+        let source = "
+            @group(3) @binding(0) var<storage, read> corners: array<vec4<f32>, 3>;
+
+            @vertex
+            fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+                return corners[index];
+            }
+
+            @fragment
+            fn fs_main() -> @location(0) vec4<f32> {
+                return vec4<f32>(1.0);
+            }
+        ";
+        std::fs::write(&path, source).unwrap();
+
+        let (device, queue) = pollster::block_on(request_headless_device(false)).unwrap();
+        let globals = GlobalsBinding::new(&device);
+        let channels = ChannelsBinding::new(&device, &queue);
+        let storage = StorageBinding::new(&device);
+        let mesh = MeshBuffers::new(&device, &Mesh::triangle());
+        let declared = DeclaredBuffers {
+            graph_path: dir.path().join("scene.passes.toml"),
+            buffers: vec![("corners".to_owned(), 48)],
+        };
+        let setup = PipelineSetup {
+            color_target: scene_target(wgpu::TextureFormat::Rgba8UnormSrgb),
+            shared_layouts: &[&globals.layout, &channels.layout, &storage.render_layout],
+            vertex_format: mesh.format,
+            depth: true,
+            storage: &declared,
+        };
+        let result = create_pipeline(
+            &device,
+            setup,
+            &path,
+            &Defines::new(),
+            &Constants::new(),
+            &mut vec![],
+        );
+        assert!(result.is_ok(), "{}", result.err().unwrap().message);
+    }
+}
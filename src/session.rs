@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+use crate::channels::ChannelInput;
+use crate::overrides::Constants;
+use crate::preprocess::Defines;
+use crate::shader::{Location, ShaderError};
+use crate::uniforms::UniformValue;
+
+/// The session file that goes with `shader`: `name.session.toml` next to
+/// `name.wgsl`.
+pub fn session_path(shader: &Path) -> PathBuf {
+    shader.with_extension("session.toml")
+}
+
+/// What's kept of a session when it's saved, and picked up again by the next
+/// run of the same shader, or a render with `--session`: the switches that
+/// are on, the values of the uniform and the overrides, the channels, the
+/// post chain and the camera. Files are relative to the shader's directory,
+/// post shaders to the post shader directory.
+///
+/// ```toml
+/// defines = ["SOFT_SHADOWS"]
+///
+/// [uniforms]
+/// scale = { f32 = 2.0 }
+/// tint = { vec4 = [1.0, 0.5, 0.0, 1.0] }
+///
+/// [overrides]
+/// steps = { u32 = 64 }
+///
+/// [[channels]]
+/// file = "noise.png"
+/// flip = false
+/// srgb = false
+/// sampling = { filter = "nearest", wrap = "repeat" }
+///
+/// [[post]]
+/// name = "blur horizontal"
+/// shader = "blur.wgsl"
+/// enabled = true
+/// params = { direction = { vec2 = [1.0, 0.0] }, kernel_radius = { f32 = 4.0 } }
+///
+/// [camera]
+/// target = [0.0, 0.5, 0.0]
+/// distance = 3.0
+/// yaw = 0.5
+/// pitch = 0.3
+/// fov = 60.0
+/// ```
+///
+/// Anything left out keeps its default. Values for uniform members and
+/// overrides the shader no longer has are ignored.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Session {
+    pub defines: Defines,
+    pub uniforms: BTreeMap<String, UniformValue>,
+    pub overrides: Constants,
+    pub channels: Vec<ChannelInput>,
+    /// `None` keeps the default chain, an empty list has no passes.
+    pub post: Option<Vec<SavedPostPass>>,
+    pub camera: Camera,
+}
+
+/// A step of the post chain, see `PostPass`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedPostPass {
+    pub name: String,
+    pub shader: PathBuf,
+    pub enabled: bool,
+    #[serde(default)]
+    pub params: BTreeMap<String, UniformValue>,
+}
+
+impl Session {
+    /// Read the session at `path`, `None` if there's no such file, as for a
+    /// shader that's never been saved.
+    pub fn load(path: &Path) -> Result<Option<Self>, ShaderError> {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(ShaderError::new(path, err.to_string())),
+        };
+        let error = |span: Option<Range<usize>>, message: String| ShaderError {
+            path: path.to_owned(),
+            location: span.map(|span| Location::at(&source, span)),
+            message,
+            source: source.clone(),
+        };
+        toml::from_str(&source)
+            .map(Some)
+            .map_err(|err| error(err.span(), err.message().to_owned()))
+    }
+
+    pub fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::{Filter, Wrap};
+    use crate::overrides::OverrideValue;
+
+    const SAVED: &str = r#"
+defines = ["SOFT_SHADOWS"]
+
+[uniforms]
+scale = { f32 = 2.0 }
+tint = { vec4 = [1.0, 0.5, 0.0, 1.0] }
+
+[overrides]
+steps = { u32 = 64 }
+
+[[channels]]
+file = "noise.png"
+srgb = false
+sampling = { filter = "nearest", wrap = "repeat" }
+
+[[post]]
+name = "blur horizontal"
+shader = "blur.wgsl"
+enabled = true
+params = { direction = { vec2 = [1.0, 0.0] } }
+
+[camera]
+distance = 5.0
+"#;
+
+    #[test]
+    fn a_saved_session_loads_and_saves_the_same() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scene.session.toml");
+        std::fs::write(&path, SAVED).unwrap();
+        let session = Session::load(&path).unwrap().unwrap();
+
+        assert_eq!(session.defines, Defines::from(["SOFT_SHADOWS".to_owned()]));
+        assert_eq!(session.uniforms["scale"], UniformValue::F32(2.0));
+        assert_eq!(session.overrides["steps"], OverrideValue::U32(64));
+        let channel = &session.channels[0];
+        assert_eq!(channel.file.as_deref(), Some(Path::new("noise.png")));
+        assert!(!channel.srgb);
+        assert_eq!(channel.sampling.filter, Filter::Nearest);
+        assert_eq!(channel.sampling.wrap, Wrap::Repeat);
+        let post = session.post.as_ref().unwrap();
+        assert_eq!(post[0].params["direction"], UniformValue::Vec2([1.0, 0.0]));
+        assert_eq!(session.camera.distance, 5.0);
+        assert_eq!(session.camera.fov, Camera::default().fov);
+
+        session.save(&path).unwrap();
+        let saved = Session::load(&path).unwrap().unwrap();
+        assert_eq!(saved.uniforms, session.uniforms);
+        assert_eq!(saved.overrides, session.overrides);
+        assert_eq!(saved.channels, session.channels);
+        assert_eq!(saved.camera, session.camera);
+    }
+
+    #[test]
+    fn a_missing_session_is_none_and_a_bad_one_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scene.session.toml");
+        assert!(Session::load(&path).unwrap().is_none());
+
+        std::fs::write(&path, "[camera]\ndistance = \"far\"\n").unwrap();
+        let error = Session::load(&path).unwrap_err();
+        assert_eq!(error.location.map(|location| location.line), Some(2));
+    }
+}
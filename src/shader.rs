@@ -0,0 +1,702 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+use crate::overrides::{self, Constants, Override};
+use crate::preprocess::{self, Defines};
+use crate::shadertoy;
+
+/// Where in a shader file a diagnostic points. Line and column are 1-based,
+/// offset and length are in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub line: u32,
+    pub column: u32,
+    pub offset: u32,
+    pub length: u32,
+}
+
+impl Location {
+    /// The location of the bytes `span` of `source`.
+    pub fn at(source: &str, span: Range<usize>) -> Self {
+        let before = &source[..span.start];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        Self {
+            line: before.matches('\n').count() as u32 + 1,
+            column: (span.start - line_start) as u32 + 1,
+            offset: span.start as u32,
+            length: span.len() as u32,
+        }
+    }
+}
+
+impl From<naga::SourceLocation> for Location {
+    fn from(loc: naga::SourceLocation) -> Self {
+        Self {
+            line: loc.line_number,
+            column: loc.line_position,
+            offset: loc.offset,
+            length: loc.length,
+        }
+    }
+}
+
+/// A shader that failed to load, parse, validate or build a pipeline.
+#[derive(Clone, Debug)]
+pub struct ShaderError {
+    pub path: PathBuf,
+    pub location: Option<Location>,
+    pub message: String,
+    /// The text that failed, kept around so the error can be shown in context.
+    pub source: String,
+}
+
+/// One line of source around an error, with the byte range of the error span
+/// that falls on it (if any).
+pub struct ExcerptLine<'a> {
+    pub number: u32,
+    pub text: &'a str,
+    pub highlight: Option<Range<usize>>,
+}
+
+impl ShaderError {
+    pub fn new(path: &Path, message: impl Into<String>) -> Self {
+        Self {
+            path: path.to_owned(),
+            location: None,
+            message: message.into(),
+            source: String::new(),
+        }
+    }
+
+    /// The lines around the error, `context` lines either side.
+    pub fn excerpt(&self, context: u32) -> Vec<ExcerptLine<'_>> {
+        let Some(loc) = self.location else {
+            return vec![];
+        };
+        let span_start = loc.offset as usize;
+        // Zero-length spans still deserve a visible marker, one column past
+        // the end of the line if that's where they point.
+        let empty = loc.length == 0;
+        let span_end = span_start + (loc.length as usize).max(1);
+        let first = loc.line.saturating_sub(context).max(1);
+        let last = loc.line + context;
+
+        let mut lines = vec![];
+        let mut line_start = 0;
+        for (number, raw) in (1..).zip(self.source.split('\n')) {
+            if (first..=last).contains(&number) {
+                let text = raw.trim_end_matches('\r');
+                let start = span_start.max(line_start);
+                let end = span_end.min(line_start + text.len() + usize::from(empty));
+                let highlight = (start < end).then(|| start - line_start..end - line_start);
+                lines.push(ExcerptLine {
+                    number,
+                    text,
+                    highlight,
+                });
+            }
+            line_start += raw.len() + 1;
+        }
+        lines
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(loc) => write!(
+                f,
+                "{}:{}:{}: {}",
+                self.path.display(),
+                loc.line,
+                loc.column,
+                self.message
+            ),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// The shader languages that can be loaded, picked by file extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Wgsl,
+    /// GLSL 450, one stage per file. Shadertoy image shaders count as GLSL
+    /// fragment shaders.
+    Glsl(naga::ShaderStage),
+    SpirV,
+}
+
+impl Format {
+    /// `.wgsl`, `.frag`, `.vert`, `.glsl` (a fragment shader) or `.spv`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "wgsl" => Some(Self::Wgsl),
+            "frag" | "glsl" => Some(Self::Glsl(naga::ShaderStage::Fragment)),
+            "vert" => Some(Self::Glsl(naga::ShaderStage::Vertex)),
+            "spv" => Some(Self::SpirV),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `path` is a shader `load_shader` can read.
+pub fn is_shader(path: &Path) -> bool {
+    Format::from_path(path).is_some()
+}
+
+/// The vertex stage for shaders that only have a fragment stage: a triangle
+/// covering the whole target, with `uv` at location 0 going from (0, 0) in the
+/// top left to (1, 1) in the bottom right. wgpu won't link outputs the
+/// fragment stage doesn't read, so the lines marked `// uv` are dropped for
+/// fragment stages without that input, see `fullscreen_source`.
+const FULLSCREEN_VERTEX: &str = "
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>, // uv
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var result: VertexOutput;
+    result.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    result.uv = uv; // uv
+    return result;
+}
+";
+
+/// `FULLSCREEN_VERTEX` for GLSL fragment shaders. naga leaves the sampling of
+/// GLSL inputs unset where WGSL sets it to `center`, and wgpu won't link the
+/// two.
+const FULLSCREEN_VERTEX_GLSL: &str = "#version 450
+layout(location = 0) out vec2 uv; // uv
+
+void main() {
+    vec2 coord = vec2(float((gl_VertexIndex << 1) & 2), float(gl_VertexIndex & 2));
+    gl_Position = vec4(coord.x * 2.0 - 1.0, 1.0 - coord.y * 2.0, 0.0, 1.0);
+    uv = coord; // uv
+}
+";
+
+/// One of the fullscreen vertex stages, without `uv` unless the fragment
+/// stage reads it.
+fn fullscreen_source(source: &str, uv: bool) -> String {
+    let lines: Vec<_> = source
+        .lines()
+        .filter(|line| uv || !line.ends_with("// uv"))
+        .collect();
+    lines.join("\n")
+}
+
+/// Whether the entry point called `name` takes an input at location 0.
+fn reads_location_zero(module: &naga::Module, name: &str) -> bool {
+    input_locations(module, name).contains(&0)
+}
+
+/// The locations of the inputs the entry point called `name` takes, directly
+/// or as struct members.
+fn input_locations(module: &naga::Module, name: &str) -> Vec<u32> {
+    let location = |binding: &Option<naga::Binding>| match binding {
+        Some(naga::Binding::Location { location, .. }) => Some(*location),
+        _ => None,
+    };
+    let Some(entry_point) = module.entry_points.iter().find(|ep| ep.name == name) else {
+        return vec![];
+    };
+    entry_point
+        .function
+        .arguments
+        .iter()
+        .flat_map(|arg| match &module.types[arg.ty].inner {
+            naga::TypeInner::Struct { members, .. } => members
+                .iter()
+                .filter_map(|member| location(&member.binding))
+                .collect(),
+            _ => location(&arg.binding).into_iter().collect::<Vec<_>>(),
+        })
+        .collect()
+}
+
+/// A shader that made it through preprocessing and validation.
+pub struct LoadedShader {
+    /// The expanded source of `module`, for reflection. Empty for SPIR-V.
+    pub source: String,
+    /// The parsed module. For GLSL it's the fragment stage.
+    pub module: naga::Module,
+    /// The vertex stage when it's in a module of its own, as with GLSL.
+    pub vertex_module: Option<naga::Module>,
+    /// The names the shader's `#ifdef`s test.
+    pub switches: Vec<String>,
+    /// The shader's `override`s and the values they were compiled with.
+    pub overrides: Vec<Override>,
+}
+
+/// One stage of a pipeline: the module it's in and its entry point.
+pub struct Stage {
+    pub module: naga::Module,
+    pub entry_point: String,
+}
+
+impl Stage {
+    /// The entry point of `stage` called `name`, or else the first one for
+    /// that stage.
+    fn find(module: &naga::Module, stage: naga::ShaderStage, name: &str) -> Option<Self> {
+        let mut candidates = module.entry_points.iter().filter(|ep| ep.stage == stage);
+        let entry_point = match candidates.clone().find(|ep| ep.name == name) {
+            Some(entry_point) => entry_point,
+            None => candidates.next()?,
+        };
+        Some(Self {
+            module: module.clone(),
+            entry_point: entry_point.name.clone(),
+        })
+    }
+
+    /// The locations of the entry point's inputs.
+    pub fn input_locations(&self) -> Vec<u32> {
+        input_locations(&self.module, &self.entry_point)
+    }
+
+    /// Hand the module to wgpu. It's been validated already, so this doesn't
+    /// go through another round of parsing.
+    pub fn create_module(&self, device: &wgpu::Device, label: &str) -> wgpu::ShaderModule {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Naga(Cow::Owned(self.module.clone())),
+        })
+    }
+}
+
+impl LoadedShader {
+    /// The vertex and fragment stages to build a pipeline from. Entry points
+    /// called `vs_main` and `fs_main` win, otherwise the first of each stage
+    /// is used. Without a vertex stage the fragment stage is drawn over the
+    /// whole target.
+    pub fn stages(&self, path: &Path) -> Result<(Stage, Stage), ShaderError> {
+        let fragment = Stage::find(&self.module, naga::ShaderStage::Fragment, "fs_main")
+            .ok_or_else(|| ShaderError::new(path, "no fragment entry point"))?;
+        let vertex_module = self.vertex_module.as_ref().unwrap_or(&self.module);
+        let vertex = match Stage::find(vertex_module, naga::ShaderStage::Vertex, "vs_main") {
+            Some(vertex) => vertex,
+            None => {
+                let uv = reads_location_zero(&fragment.module, &fragment.entry_point);
+                let module =
+                    naga::front::wgsl::parse_str(&fullscreen_source(FULLSCREEN_VERTEX, uv))
+                        .expect("the fullscreen vertex stage is valid WGSL");
+                Stage::find(&module, naga::ShaderStage::Vertex, "vs_main")
+                    .expect("the fullscreen vertex stage has an entry point")
+            }
+        };
+        Ok((vertex, fragment))
+    }
+
+    /// Whether there's no vertex stage, so `stages` draws the fragment stage
+    /// over the whole target rather than the mesh.
+    pub fn draws_fullscreen(&self) -> bool {
+        let vertex_module = self.vertex_module.as_ref().unwrap_or(&self.module);
+        Stage::find(vertex_module, naga::ShaderStage::Vertex, "vs_main").is_none()
+    }
+
+    /// The `@compute` entry point called `name`, or the first one if there's
+    /// no name, and its workgroup size.
+    pub fn compute_stage(
+        &self,
+        path: &Path,
+        name: Option<&str>,
+    ) -> Result<(Stage, [u32; 3]), ShaderError> {
+        let mut candidates = self
+            .module
+            .entry_points
+            .iter()
+            .filter(|ep| ep.stage == naga::ShaderStage::Compute);
+        let entry_point = match name {
+            Some(name) => candidates.find(|ep| ep.name == name).ok_or_else(|| {
+                ShaderError::new(path, format!("no compute entry point called {name}"))
+            })?,
+            None => candidates
+                .next()
+                .ok_or_else(|| ShaderError::new(path, "no compute entry point"))?,
+        };
+        let stage = Stage {
+            module: self.module.clone(),
+            entry_point: entry_point.name.clone(),
+        };
+        Ok((stage, entry_point.workgroup_size))
+    }
+}
+
+/// Read a shader from disk in whichever language its extension says, and
+/// make sure it parses and validates before it gets anywhere near the device.
+/// Directives are expanded with `defines` defined and overrides set from
+/// `constants`; SPIR-V has neither. `files` gets every file the shader
+/// depends on, even if loading fails.
+pub fn load_shader(
+    path: &Path,
+    defines: &Defines,
+    constants: &Constants,
+    files: &mut Vec<PathBuf>,
+) -> Result<LoadedShader, ShaderError> {
+    match Format::from_path(path) {
+        Some(Format::Wgsl) => load_wgsl(path, defines, constants, files),
+        Some(Format::Glsl(stage)) => load_glsl(path, stage, defines, files),
+        Some(Format::SpirV) => load_spirv(path, files),
+        None => {
+            files.push(path.to_owned());
+            Err(ShaderError::new(
+                path,
+                "unknown shader type, expected .wgsl, .frag, .vert, .glsl or .spv",
+            ))
+        }
+    }
+}
+
+fn load_wgsl(
+    path: &Path,
+    defines: &Defines,
+    constants: &Constants,
+    files: &mut Vec<PathBuf>,
+) -> Result<LoadedShader, ShaderError> {
+    let expanded = preprocess::expand(path, defines, constants, files)?;
+    let module = validate_wgsl(path, &expanded.text).map_err(|err| expanded.map_error(err))?;
+    let overrides = overrides::reflect(&module, &expanded.overrides);
+    Ok(LoadedShader {
+        source: expanded.text,
+        module,
+        vertex_module: None,
+        switches: expanded.switches,
+        overrides,
+    })
+}
+
+/// GLSL keeps each stage in a file of its own. The other stage is looked for
+/// next to `path`: `name.vert` goes with `name.frag` or `name.glsl`. A
+/// fragment shader without one gets `FULLSCREEN_VERTEX_GLSL`, but a vertex
+/// shader needs a `name.frag`, or else a `name.glsl`. The fragment shader can
+/// also be a Shadertoy image shader, see `shadertoy::wrap`.
+fn load_glsl(
+    path: &Path,
+    stage: naga::ShaderStage,
+    defines: &Defines,
+    files: &mut Vec<PathBuf>,
+) -> Result<LoadedShader, ShaderError> {
+    let fragment_path = match stage {
+        naga::ShaderStage::Fragment => path.to_owned(),
+        _ => {
+            let glsl = path.with_extension("glsl");
+            let frag = path.with_extension("frag");
+            if !frag.exists() && glsl.exists() {
+                glsl
+            } else {
+                frag
+            }
+        }
+    };
+    let vertex_path = path.with_extension("vert");
+    // Watched even while they don't exist, so creating one triggers a reload.
+    for stage_path in [&fragment_path, &vertex_path] {
+        if !files.contains(stage_path) {
+            files.push(stage_path.clone());
+        }
+    }
+    if !fragment_path.exists() {
+        return Err(ShaderError::new(
+            path,
+            format!(
+                "a vertex shader needs a fragment shader next to it, {} doesn't exist",
+                fragment_path.display()
+            ),
+        ));
+    }
+
+    let load = |path: &Path, stage, files: &mut Vec<PathBuf>| {
+        let mut expanded = preprocess::expand_glsl(path, files)?;
+        if stage == naga::ShaderStage::Fragment && shadertoy::is_image_shader(&expanded.text) {
+            shadertoy::wrap(path, &mut expanded)?;
+        }
+        let module = validate_glsl(path, stage, defines, &expanded.text)
+            .map_err(|err| expanded.map_error(err))?;
+        Ok::<_, ShaderError>((expanded, module))
+    };
+    let (fragment, module) = load(&fragment_path, naga::ShaderStage::Fragment, files)?;
+    let mut switches = fragment.switches;
+    let vertex_module = if vertex_path.exists() {
+        let (vertex, module) = load(&vertex_path, naga::ShaderStage::Vertex, files)?;
+        for switch in vertex.switches {
+            if !switches.contains(&switch) {
+                switches.push(switch);
+            }
+        }
+        module
+    } else {
+        // GLSL entry points are always called `main`.
+        let uv = reads_location_zero(&module, "main");
+        let options = naga::front::glsl::Options::from(naga::ShaderStage::Vertex);
+        naga::front::glsl::Parser::default()
+            .parse(&options, &fullscreen_source(FULLSCREEN_VERTEX_GLSL, uv))
+            .expect("the fullscreen vertex stage is valid GLSL")
+    };
+    Ok(LoadedShader {
+        source: fragment.text,
+        module,
+        vertex_module: Some(vertex_module),
+        switches,
+        overrides: vec![],
+    })
+}
+
+fn load_spirv(path: &Path, files: &mut Vec<PathBuf>) -> Result<LoadedShader, ShaderError> {
+    if !files.iter().any(|file| file == path) {
+        files.push(path.to_owned());
+    }
+    let bytes = std::fs::read(path).map_err(|err| ShaderError::new(path, err.to_string()))?;
+    let module = naga::front::spv::parse_u8_slice(&bytes, &Default::default())
+        .map_err(|err| ShaderError::new(path, err.to_string()))?;
+    // There's no text to point into.
+    Validator::new(ValidationFlags::all(), Capabilities::empty())
+        .validate(&module)
+        .map_err(|err| ShaderError::new(path, error_chain(&err)))?;
+    Ok(LoadedShader {
+        source: String::new(),
+        module,
+        vertex_module: None,
+        switches: vec![],
+        overrides: vec![],
+    })
+}
+
+pub fn validate_wgsl(path: &Path, source: &str) -> Result<naga::Module, ShaderError> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|err| ShaderError {
+        path: path.to_owned(),
+        location: err.location(source).map(Into::into),
+        message: err.to_string(),
+        source: source.to_owned(),
+    })?;
+    validate(path, &module, source)?;
+    Ok(module)
+}
+
+/// Parse one GLSL stage. `defines` are passed to naga's preprocessor.
+pub fn validate_glsl(
+    path: &Path,
+    stage: naga::ShaderStage,
+    defines: &Defines,
+    source: &str,
+) -> Result<naga::Module, ShaderError> {
+    let options = naga::front::glsl::Options {
+        stage,
+        defines: defines
+            .iter()
+            .map(|name| (name.clone(), "1".to_owned()))
+            .collect(),
+    };
+    let module = naga::front::glsl::Parser::default()
+        .parse(&options, source)
+        .map_err(|errors| {
+            let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+            ShaderError {
+                path: path.to_owned(),
+                location: errors.first().map(|err| err.meta.location(source).into()),
+                message: messages.join("; "),
+                source: source.to_owned(),
+            }
+        })?;
+    validate(path, &module, source)?;
+    Ok(module)
+}
+
+fn validate(path: &Path, module: &naga::Module, source: &str) -> Result<(), ShaderError> {
+    Validator::new(ValidationFlags::all(), Capabilities::empty())
+        .validate(module)
+        .map_err(|err| ShaderError {
+            path: path.to_owned(),
+            location: err.location(source).map(Into::into),
+            message: error_chain(&err),
+            source: source.to_owned(),
+        })?;
+    Ok(())
+}
+
+/// Naga nests the interesting part of validation errors a few levels deep, so
+/// flatten the whole chain into one line.
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(next) = source {
+        message.push_str(": ");
+        message.push_str(&next.to_string());
+        source = next.source();
+    }
+    message
+}
+
+/// Error scopes are a stack on the device, shared by every thread, so only one
+/// build at a time may have one open. Otherwise builds running side by side
+/// pop each other's errors.
+static ERROR_SCOPE: Mutex<()> = Mutex::new(());
+
+/// Run `f` inside a validation error scope. Anything wgpu complains about that
+/// naga didn't already catch, like a missing entry point, comes back as a
+/// `ShaderError` for `path` instead of hitting the uncaptured error handler.
+pub fn capture_errors<T>(
+    device: &wgpu::Device,
+    path: &Path,
+    f: impl FnOnce() -> T,
+) -> Result<T, ShaderError> {
+    // A build that panicked while holding the lock still popped its scope.
+    let _lock = ERROR_SCOPE.lock().unwrap_or_else(PoisonError::into_inner);
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = panic::catch_unwind(AssertUnwindSafe(f));
+    let error = pollster::block_on(device.pop_error_scope());
+    let value = value.unwrap_or_else(|payload| panic::resume_unwind(payload));
+    match error {
+        Some(err) => Err(ShaderError::new(path, err.to_string())),
+        None => Ok(value),
+    }
+}
+
+/// Run a build of the shader at `path`, turning a panic in naga or wgpu into a
+/// `ShaderError` so the build still reports back.
+pub fn catch_panic<T>(
+    path: &Path,
+    build: impl FnOnce() -> Result<T, ShaderError>,
+) -> Result<T, ShaderError> {
+    panic::catch_unwind(AssertUnwindSafe(build)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown error");
+        Err(ShaderError::new(
+            path,
+            format!("the build crashed: {message}"),
+        ))
+    })
+}
+
+/// Whether two paths name the same file, however they were spelled. Falls back
+/// to comparing the paths as given when either can't be resolved, e.g. because
+/// it was just deleted.
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Whether any of `changed` is one of a shader's `files`.
+pub fn depends_on(files: &[PathBuf], changed: &[PathBuf]) -> bool {
+    files
+        .iter()
+        .any(|file| changed.iter().any(|path| same_file(file, path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_at(source: &str, span: Range<usize>) -> ShaderError {
+        ShaderError {
+            path: PathBuf::from("shader.wgsl"),
+            location: Some(Location::at(source, span)),
+            message: "bad".to_owned(),
+            source: source.to_owned(),
+        }
+    }
+
+    fn lines(err: &ShaderError, context: u32) -> Vec<(u32, &str, Option<Range<usize>>)> {
+        err.excerpt(context)
+            .into_iter()
+            .map(|line| (line.number, line.text, line.highlight))
+            .collect()
+    }
+
+    #[test]
+    fn excerpt_highlights_the_span_with_context() {
+        let source = "one\ntwo\nthree bad\nfour\nfive\nsix";
+        let err = error_at(source, 14..17);
+        assert_eq!(
+            lines(&err, 1),
+            [
+                (2, "two", None),
+                (3, "three bad", Some(6..9)),
+                (4, "four", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn excerpt_stops_at_the_ends_of_the_file() {
+        let err = error_at("bad\r\ntwo", 0..3);
+        assert_eq!(lines(&err, 3), [(1, "bad", Some(0..3)), (2, "two", None)]);
+    }
+
+    #[test]
+    fn excerpt_spans_across_lines_highlight_each_line() {
+        let err = error_at("a {\n  b\n}", 2..9);
+        assert_eq!(
+            lines(&err, 0),
+            [(1, "a {", Some(2..3))],
+            "only the error's first line is shown without context"
+        );
+        assert_eq!(
+            lines(&err, 1),
+            [(1, "a {", Some(2..3)), (2, "  b", Some(0..3))]
+        );
+    }
+
+    #[test]
+    fn excerpt_marks_empty_spans() {
+        let err = error_at("fn main() {", 11..11);
+        assert_eq!(lines(&err, 0), [(1, "fn main() {", Some(11..12))]);
+        let err = error_at("fn main() {\r\n}", 11..11);
+        assert_eq!(
+            lines(&err, 1),
+            [(1, "fn main() {", Some(11..12)), (2, "}", None)]
+        );
+        let err = error_at("fn main() {", 3..3);
+        assert_eq!(lines(&err, 0), [(1, "fn main() {", Some(3..4))]);
+    }
+
+    #[test]
+    fn vertex_shaders_pair_with_a_frag_or_else_a_glsl() {
+        let dir = tempfile::tempdir().unwrap();
+        let vertex = dir.path().join("mesh.vert");
+        let fragment = |name: &str| {
+            let source = format!(
+                "#version 450\n// {name}\nlayout(location = 0) out vec4 color;\n\
+                 void main() {{ color = vec4(1.0); }}\n"
+            );
+            std::fs::write(dir.path().join(name), source).unwrap();
+        };
+        let load = || load_shader(&vertex, &Defines::new(), &Constants::new(), &mut vec![]);
+        std::fs::write(
+            &vertex,
+            "#version 450\nvoid main() { gl_Position = vec4(0.0); }\n",
+        )
+        .unwrap();
+
+        assert!(load().is_err(), "a vertex shader on its own can't draw");
+        fragment("mesh.glsl");
+        assert!(load().unwrap().source.contains("// mesh.glsl"));
+        fragment("mesh.frag");
+        assert!(load().unwrap().source.contains("// mesh.frag"));
+    }
+
+    #[test]
+    fn errors_without_a_location_have_no_excerpt() {
+        assert!(ShaderError::new(Path::new("shader.wgsl"), "bad")
+            .excerpt(2)
+            .is_empty());
+    }
+}
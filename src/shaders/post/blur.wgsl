@@ -0,0 +1,53 @@
+// One axis of a separable gaussian blur.
+
+struct Params {
+    // (1, 0) for horizontal or (0, 1) for vertical.
+    // @ui drag min=-1 max=1
+    direction: vec2<f32>,
+    // In pixels; the kernel is 2 * kernel_radius + 1 wide.
+    // @ui slider min=0 max=120 step=1 default=8
+    kernel_radius: f32,
+};
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// One triangle that covers the whole screen.
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    var result: VertexOutput;
+    result.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    result.uv = uv;
+    return result;
+}
+
+@fragment
+fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let radius = i32(max(params.kernel_radius, 0.0));
+    // A radius of 0 leaves the image as it is.
+    if radius == 0 {
+        return textureSampleLevel(source, source_sampler, vertex.uv, 0.0);
+    }
+    let texel = params.direction / vec2<f32>(textureDimensions(source));
+    // Most of a gaussian's weight sits within 3 sigma.
+    let sigma = max(f32(radius) / 3.0, 1.0);
+    var sum = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var i = -radius; i <= radius; i += 1) {
+        let x = f32(i);
+        let weight = exp(-(x * x) / (2.0 * sigma * sigma));
+        sum += textureSampleLevel(source, source_sampler, vertex.uv + texel * x, 0.0) * weight;
+        total += weight;
+    }
+    return sum / total;
+}
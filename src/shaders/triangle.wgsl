@@ -14,12 +14,17 @@ fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
     return result;
 }
 
+struct Params {
+    // @ui color default=0,0,1,1
+    color: vec4<f32>,
+};
+
 @group(0)
-@binding(0) 
-var<uniform> color: vec4<f32>;
+@binding(0)
+var<uniform> params: Params;
 
 @fragment
 fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    return color;
+    return params.color;
     // return vec4<f32>(color.x, color.y, color.z, color.w);
 }
\ No newline at end of file
@@ -0,0 +1,213 @@
+use std::path::Path;
+
+use crate::preprocess::Expanded;
+use crate::shader::{Location, ShaderError};
+
+/// Goes in front of a Shadertoy shader: the inputs Shadertoy declares, read
+/// from the globals at `@group(1)` and the channels at `@group(2)`.
+const HEADER: &str = "#version 450
+precision highp float;
+precision highp int;
+
+layout(set = 1, binding = 0) uniform ShadertoyGlobals {
+    vec4 _shadertoy_mouse;
+    vec4 iDate;
+    vec2 _shadertoy_resolution;
+    float iTime;
+    float iTimeDelta;
+    uint _shadertoy_frame;
+    uint _shadertoy_mouse_buttons;
+};
+
+layout(set = 2, binding = 0) uniform texture2D _shadertoy_channel0;
+layout(set = 2, binding = 1) uniform sampler _shadertoy_sampler0;
+layout(set = 2, binding = 2) uniform texture2D _shadertoy_channel1;
+layout(set = 2, binding = 3) uniform sampler _shadertoy_sampler1;
+layout(set = 2, binding = 4) uniform texture2D _shadertoy_channel2;
+layout(set = 2, binding = 5) uniform sampler _shadertoy_sampler2;
+layout(set = 2, binding = 6) uniform texture2D _shadertoy_channel3;
+layout(set = 2, binding = 7) uniform sampler _shadertoy_sampler3;
+#define iChannel0 sampler2D(_shadertoy_channel0, _shadertoy_sampler0)
+#define iChannel1 sampler2D(_shadertoy_channel1, _shadertoy_sampler1)
+#define iChannel2 sampler2D(_shadertoy_channel2, _shadertoy_sampler2)
+#define iChannel3 sampler2D(_shadertoy_channel3, _shadertoy_sampler3)
+
+vec3 iResolution;
+vec4 iMouse;
+int iFrame;
+float iFrameRate;
+vec3 iChannelResolution[4];
+float iChannelTime[4];
+";
+
+/// Goes after a Shadertoy shader: fills in the inputs that need computing and
+/// calls `mainImage` with Shadertoy's bottom-left origin. In a buffer pass the
+/// rows are stored bottom-up instead of flipped, so sampling a buffer with
+/// Shadertoy's coordinates finds what was drawn there, and alpha is kept.
+const FOOTER: &str = "
+layout(location = 0) out vec4 _shadertoy_color;
+
+// Shadertoy puts colors on screen as they are, but sRGB targets encode
+// what's written to them. Undo that ahead of time.
+vec3 _shadertoy_decode(vec3 color) {
+#ifdef SRGB_TARGET
+    color = clamp(color, 0.0, 1.0);
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(color / 12.92, high, step(0.04045, color));
+#else
+    return color;
+#endif
+}
+
+void main() {
+    iResolution = vec3(_shadertoy_resolution, 1.0);
+    float height = _shadertoy_resolution.y;
+    vec2 click = vec2(_shadertoy_mouse.z, height - _shadertoy_mouse.w);
+    bool down = (_shadertoy_mouse_buttons & 1u) != 0u;
+    iMouse = vec4(_shadertoy_mouse.x, height - _shadertoy_mouse.y, down ? click : -click);
+    iFrame = int(_shadertoy_frame);
+    iFrameRate = iTimeDelta > 0.0 ? 1.0 / iTimeDelta : 60.0;
+    iChannelResolution[0] = vec3(textureSize(iChannel0, 0), 1.0);
+    iChannelResolution[1] = vec3(textureSize(iChannel1, 0), 1.0);
+    iChannelResolution[2] = vec3(textureSize(iChannel2, 0), 1.0);
+    iChannelResolution[3] = vec3(textureSize(iChannel3, 0), 1.0);
+    // Audio channels play along with the clock from the start.
+    iChannelTime = float[4](iTime, iTime, iTime, iTime);
+
+    vec4 color = vec4(0.0);
+#ifdef BUFFER_PASS
+    mainImage(color, gl_FragCoord.xy);
+    _shadertoy_color = color;
+#else
+    mainImage(color, vec2(gl_FragCoord.x, height - gl_FragCoord.y));
+    // Shadertoy ignores alpha on screen.
+    _shadertoy_color = vec4(_shadertoy_decode(color.rgb), 1.0);
+#endif
+}
+";
+
+/// Shadertoy features there's nothing to hook up to, and why.
+const UNSUPPORTED: &[(&str, &str)] = &[
+    ("iSampleRate", "there's no sound output"),
+    ("mainSound", "sound shaders can't be played"),
+    ("mainVR", "there's no VR output"),
+    ("mainCubemap", "cubemap passes aren't supported"),
+    ("samplerCube", "channels are 2D textures"),
+];
+
+/// Whether `source` is a Shadertoy image shader, i.e. it has a `mainImage`
+/// and no `main`.
+pub fn is_image_shader(source: &str) -> bool {
+    find_call(source, "mainImage").is_some() && find_call(source, "main").is_none()
+}
+
+/// Make a Shadertoy image shader a complete GLSL fragment shader, with
+/// `iTime`, `iTimeDelta`, `iFrame`, `iFrameRate`, `iResolution`, `iMouse`,
+/// `iDate`, `iChannel0` to `iChannel3`, `iChannelResolution` and
+/// `iChannelTime` declared. The shader is used as is; it shouldn't have a
+/// `#version`. `iMouse.xy` follows the cursor even when no button is down.
+pub fn wrap(path: &Path, expanded: &mut Expanded) -> Result<(), ShaderError> {
+    for (name, reason) in UNSUPPORTED {
+        if let Some(offset) = find_identifier(&expanded.text, name) {
+            let err = ShaderError {
+                path: path.to_owned(),
+                location: Some(Location::at(&expanded.text, offset..offset + name.len())),
+                message: format!("Shadertoy's {name} isn't supported, {reason}"),
+                source: expanded.text.clone(),
+            };
+            return Err(expanded.map_error(err));
+        }
+    }
+    expanded.wrap(HEADER, FOOTER);
+    Ok(())
+}
+
+/// The offset of the first use of `name` as an identifier outside of a line
+/// comment.
+fn find_identifier(source: &str, name: &str) -> Option<usize> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut line_start = 0;
+    for line in source.split_inclusive('\n') {
+        let code = line.split("//").next().unwrap_or_default();
+        for (index, _) in code.match_indices(name) {
+            let before = code[..index].chars().next_back();
+            let after = code[index + name.len()..].chars().next();
+            if !before.is_some_and(is_ident) && !after.is_some_and(is_ident) {
+                return Some(line_start + index);
+            }
+        }
+        line_start += line.len();
+    }
+    None
+}
+
+/// Like `find_identifier`, but only where `name` is followed by `(`.
+fn find_call(source: &str, name: &str) -> Option<usize> {
+    let mut rest = source;
+    let mut skipped = 0;
+    while let Some(offset) = find_identifier(rest, name) {
+        let after = &rest[offset + name.len()..];
+        if after.trim_start().starts_with('(') {
+            return Some(skipped + offset);
+        }
+        skipped += offset + name.len();
+        rest = after;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess;
+
+    #[test]
+    fn main_image_without_main_is_an_image_shader() {
+        assert!(is_image_shader(
+            "void mainImage(out vec4 color, in vec2 coord) {\n    color = vec4(1.0);\n}\n"
+        ));
+        assert!(is_image_shader("void mainImage (out vec4 c, vec2 p) {}"));
+    }
+
+    #[test]
+    fn shaders_with_a_main_are_not_image_shaders() {
+        assert!(!is_image_shader(
+            "void mainImage(out vec4 c, vec2 p) {}\nvoid main() { mainImage(color, coord); }"
+        ));
+        assert!(!is_image_shader("void main() {}"));
+    }
+
+    #[test]
+    fn main_image_has_to_be_a_function() {
+        assert!(!is_image_shader("// void mainImage(out vec4 c, vec2 p) {}"));
+        assert!(!is_image_shader("void mainImage2(out vec4 c, vec2 p) {}"));
+        assert!(!is_image_shader("float mainImage;"));
+    }
+
+    #[test]
+    fn a_main_in_a_comment_or_name_does_not_count() {
+        assert!(is_image_shader(
+            "// no main() here\nvoid mainImage(out vec4 c, vec2 p) { domain(p); }"
+        ));
+        assert!(is_image_shader(
+            "float main_ = 1.0;\nvoid mainImage(out vec4 c, vec2 p) {}"
+        ));
+    }
+
+    #[test]
+    fn unsupported_inputs_are_reported_where_they_are_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("toy.glsl");
+        let source = "void mainImage(out vec4 c, vec2 p) {\n    c = vec4(iSampleRate);\n}\n";
+        std::fs::write(&path, source).unwrap();
+        let mut expanded = preprocess::expand_glsl(&path, &mut vec![]).unwrap();
+
+        let err = wrap(&path, &mut expanded).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Shadertoy's iSampleRate isn't supported, there's no sound output"
+        );
+        let location = err.location.unwrap();
+        assert_eq!((location.line, location.column), (2, 14));
+    }
+}
@@ -0,0 +1,215 @@
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Result};
+
+use crate::overrides::Constants;
+use crate::preprocess::Defines;
+use crate::shader::{self, ShaderError};
+
+/// How many samples one dispatch of a sound shader writes. Small enough that
+/// even a workgroup size of 1 stays under the dispatch limit.
+const BLOCK_SAMPLES: u32 = 32768;
+
+/// Bytes per stereo sample in the storage buffer, a `vec2<f32>`.
+const SAMPLE_SIZE: u64 = 8;
+
+/// Where a dispatch of a sound shader is in the sound. Declare it in WGSL as
+///
+/// ```wgsl
+/// struct Sound {
+///     // The time in seconds of the block's first sample.
+///     time: f32,
+///     sample_rate: f32,
+///     // The index of the block's first sample in the whole sound.
+///     offset: u32,
+///     // How many samples the block has. Invocations past it write nothing.
+///     count: u32,
+/// };
+///
+/// @group(0) @binding(0)
+/// var<uniform> sound: Sound;
+/// @group(0) @binding(1)
+/// var<storage, read_write> samples: array<vec2<f32>>;
+/// ```
+///
+/// Each invocation writes the left and right sample at its
+/// `global_invocation_id.x`, from -1 to 1, which is
+/// `sound.time + f32(id.x) / sound.sample_rate` seconds into the sound.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct Block {
+    time: f32,
+    sample_rate: f32,
+    offset: u32,
+    count: u32,
+}
+
+/// A sound shader's compute pipeline, ready to render samples.
+pub struct SoundShader {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// The entry point's `@workgroup_size`.
+    workgroup_size: [u32; 3],
+}
+
+impl SoundShader {
+    /// Build the pipeline for the first compute entry point in `path`.
+    pub fn new(device: &wgpu::Device, path: &Path, defines: &Defines) -> Result<Self, ShaderError> {
+        let mut files = vec![];
+        let loaded = shader::load_shader(path, defines, &Constants::new(), &mut files)?;
+        let (stage, workgroup_size) = loaded.compute_stage(path, None)?;
+
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Sound Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<Block>() as u64
+                            ),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sound Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = shader::capture_errors(device, path, || {
+            let module = stage.create_module(device, "Sound Shader");
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Sound Pipeline"),
+                layout: Some(&layout),
+                module: &module,
+                entry_point: &stage.entry_point,
+            })
+        })?;
+
+        Ok(Self {
+            pipeline,
+            bind_group_layout,
+            workgroup_size,
+        })
+    }
+
+    /// Render `length` seconds of stereo samples at `sample_rate`, a block at a
+    /// time, reading each back before the next.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sample_rate: u32,
+        length: f32,
+    ) -> Result<Vec<[f32; 2]>> {
+        let total = (length as f64 * sample_rate as f64).round() as u64;
+        let total = u32::try_from(total).map_err(|_| eyre!("the sound is too long"))?;
+
+        let block_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sound Block Buffer"),
+            size: std::mem::size_of::<Block>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let samples_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sound Samples Buffer"),
+            size: BLOCK_SAMPLES as u64 * SAMPLE_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sound Readback Buffer"),
+            size: samples_buffer.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sound Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: block_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: samples_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut samples = Vec::with_capacity(total as usize);
+        for offset in (0..total).step_by(BLOCK_SAMPLES as usize) {
+            let count = BLOCK_SAMPLES.min(total - offset);
+            let block = Block {
+                // In f64 so the blocks of a long sound don't drift.
+                time: (offset as f64 / sample_rate as f64) as f32,
+                sample_rate: sample_rate as f32,
+                offset,
+                count,
+            };
+            queue.write_buffer(&block_buffer, 0, bytemuck::bytes_of(&block));
+
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Sound Encoder"),
+            });
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Sound Pass"),
+                });
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(count.div_ceil(self.workgroup_size[0].max(1)), 1, 1);
+            }
+            let size = count as u64 * SAMPLE_SIZE;
+            encoder.copy_buffer_to_buffer(&samples_buffer, 0, &readback, 0, size);
+            queue.submit(Some(encoder.finish()));
+
+            let slice = readback.slice(..size);
+            let (sender, receiver) = std::sync::mpsc::channel();
+            slice.map_async(wgpu::MapMode::Read, move |result| {
+                _ = sender.send(result);
+            });
+            device.poll(wgpu::Maintain::Wait);
+            receiver.recv()??;
+            samples.extend_from_slice(bytemuck::cast_slice(&slice.get_mapped_range()));
+            readback.unmap();
+        }
+        Ok(samples)
+    }
+}
+
+/// Write stereo `samples` to a 16-bit WAV file. Samples outside -1 to 1 are
+/// clipped.
+pub fn write_wav(path: &Path, sample_rate: u32, samples: &[[f32; 2]]) -> Result<()> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for sample in samples.iter().flatten() {
+        // NaN clamps to NaN, and casts to 0.
+        writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
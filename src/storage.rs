@@ -0,0 +1,306 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+use crate::shader::{ShaderError, Stage};
+
+/// How many storage buffers a pass graph can declare.
+pub const STORAGE_BUFFER_SLOTS: u32 = 4;
+
+/// How many storage textures a pass graph can declare.
+pub const STORAGE_TEXTURE_SLOTS: u32 = 4;
+
+/// The format of storage textures. The same as buffers', so they can be
+/// sampled the same way.
+pub const STORAGE_TEXTURE_FORMAT: wgpu::TextureFormat = crate::passes::BUFFER_FORMAT;
+
+/// The size of the buffer bound to storage buffer slots nothing is declared
+/// for.
+const PLACEHOLDER_SIZE: u64 = 16;
+
+/// The storage a pass graph declares, at `@group(3)`. Storage buffers take
+/// bindings 0 to 3 in the order they're listed, storage textures bindings 4
+/// to 7:
+///
+/// ```wgsl
+/// @group(3) @binding(0)
+/// var<storage, read_write> particles: array<Particle>;
+/// @group(3) @binding(4)
+/// var trail: texture_storage_2d<rgba16float, write>;
+/// ```
+///
+/// Compute shaders get `compute_layout` and can write both. Vertex and
+/// fragment shaders get `render_layout`, with the buffers only, read-only as
+/// `var<storage, read>`; they sample storage textures on a channel instead.
+/// Every slot is always bound, the ones nothing is declared for to a small
+/// empty placeholder. A shader whose buffers don't fit what's bound fails to
+/// build, see `DeclaredBuffers::check`.
+pub struct StorageBinding {
+    pub compute_layout: Arc<wgpu::BindGroupLayout>,
+    pub render_layout: Arc<wgpu::BindGroupLayout>,
+    empty_buffer: wgpu::Buffer,
+    empty_texture: wgpu::TextureView,
+}
+
+impl StorageBinding {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer_entry = |binding, read_only, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let compute_entries: Vec<_> = (0..STORAGE_BUFFER_SLOTS)
+            .map(|slot| buffer_entry(slot, false, wgpu::ShaderStages::COMPUTE))
+            .chain(
+                (0..STORAGE_TEXTURE_SLOTS).map(|slot| wgpu::BindGroupLayoutEntry {
+                    binding: STORAGE_BUFFER_SLOTS + slot,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: STORAGE_TEXTURE_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                }),
+            )
+            .collect();
+        let compute_layout = Arc::new(device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Compute Storage Bind Group Layout"),
+                entries: &compute_entries,
+            },
+        ));
+
+        let render_entries: Vec<_> = (0..STORAGE_BUFFER_SLOTS)
+            .map(|slot| buffer_entry(slot, true, wgpu::ShaderStages::VERTEX_FRAGMENT))
+            .collect();
+        let render_layout = Arc::new(device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Render Storage Bind Group Layout"),
+                entries: &render_entries,
+            },
+        ));
+
+        let empty_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Empty Storage Buffer"),
+            size: PLACEHOLDER_SIZE,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let empty_texture = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Empty Storage Texture"),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: STORAGE_TEXTURE_FORMAT,
+                usage: wgpu::TextureUsages::STORAGE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            compute_layout,
+            render_layout,
+            empty_buffer,
+            empty_texture,
+        }
+    }
+
+    /// A bind group for compute passes with `buffers` and `textures` in the
+    /// first slots.
+    pub fn compute_bind_group(
+        &self,
+        device: &wgpu::Device,
+        buffers: &[&wgpu::Buffer],
+        textures: &[&wgpu::TextureView],
+    ) -> wgpu::BindGroup {
+        let mut entries = self.buffer_entries(buffers);
+        entries.extend((0..STORAGE_TEXTURE_SLOTS).map(|slot| {
+            let texture = textures
+                .get(slot as usize)
+                .copied()
+                .unwrap_or(&self.empty_texture);
+            wgpu::BindGroupEntry {
+                binding: STORAGE_BUFFER_SLOTS + slot,
+                resource: wgpu::BindingResource::TextureView(texture),
+            }
+        }));
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compute Storage Bind Group"),
+            layout: &self.compute_layout,
+            entries: &entries,
+        })
+    }
+
+    /// A bind group for render passes with `buffers` in the first slots.
+    pub fn render_bind_group(
+        &self,
+        device: &wgpu::Device,
+        buffers: &[&wgpu::Buffer],
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Render Storage Bind Group"),
+            layout: &self.render_layout,
+            entries: &self.buffer_entries(buffers),
+        })
+    }
+
+    fn buffer_entries<'a>(&'a self, buffers: &[&'a wgpu::Buffer]) -> Vec<wgpu::BindGroupEntry<'a>> {
+        (0..STORAGE_BUFFER_SLOTS)
+            .map(|slot| {
+                let buffer = buffers
+                    .get(slot as usize)
+                    .copied()
+                    .unwrap_or(&self.empty_buffer);
+                wgpu::BindGroupEntry {
+                    binding: slot,
+                    resource: buffer.as_entire_binding(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The storage buffers a pass graph declares, which pipelines are checked
+/// against when they're built.
+#[derive(Clone, Debug, Default)]
+pub struct DeclaredBuffers {
+    /// The graph file the buffers are declared in.
+    pub graph_path: PathBuf,
+    /// Each buffer's name and size in bytes, in binding order.
+    pub buffers: Vec<(String, u64)>,
+}
+
+impl DeclaredBuffers {
+    /// Check that every storage buffer `stages` use at `@group(3)` fits in
+    /// what's bound there: the buffer declared for its binding, or else the
+    /// placeholder. wgpu would only notice when the pass runs. The graph is
+    /// added to `files`, so the pipeline is checked again when it changes.
+    pub fn check(
+        &self,
+        path: &Path,
+        stages: &[&Stage],
+        files: &mut Vec<PathBuf>,
+    ) -> Result<(), ShaderError> {
+        if !files.contains(&self.graph_path) {
+            files.push(self.graph_path.clone());
+        }
+        for stage in stages {
+            let module = &stage.module;
+            let Some(index) = module
+                .entry_points
+                .iter()
+                .position(|ep| ep.name == stage.entry_point)
+            else {
+                continue;
+            };
+            // Only bindings the entry point uses are checked by wgpu, too.
+            let Ok(info) =
+                Validator::new(ValidationFlags::all(), Capabilities::empty()).validate(module)
+            else {
+                continue;
+            };
+            let uses = info.get_entry_point(index);
+            for (handle, var) in module.global_variables.iter() {
+                let Some(binding) = &var.binding else {
+                    continue;
+                };
+                let is_storage = matches!(var.space, naga::AddressSpace::Storage { .. });
+                if !is_storage || binding.group != 3 || uses[handle].is_empty() {
+                    continue;
+                }
+                let needed = module.types[var.ty].inner.size(&module.constants) as u64;
+                let declared = self.buffers.get(binding.binding as usize);
+                let available = declared.map_or(PLACEHOLDER_SIZE, |(_, size)| *size);
+                if needed <= available {
+                    continue;
+                }
+                let graph = self.graph_path.display();
+                let bound = match declared {
+                    Some((name, size)) => format!("{name} in {graph} is only {size} bytes"),
+                    None => format!("{graph} declares no storage buffer for it"),
+                };
+                let message = format!(
+                    "`{}` at @group(3) @binding({}) needs at least {needed} bytes, but {bound}",
+                    var.name.as_deref().unwrap_or_default(),
+                    binding.binding,
+                );
+                return Err(ShaderError::new(path, message));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+        struct Particle { position: vec4<f32>, velocity: vec4<f32> }
+        @group(3) @binding(1) var<storage, read_write> particles: array<Particle, 4>;
+        @group(3) @binding(2) var<storage, read_write> unused: array<Particle, 4>;
+
+        @compute @workgroup_size(1)
+        fn main() {
+            particles[0].position = vec4<f32>(1.0);
+        }
+    ";
+
+    fn stage() -> Stage {
+        Stage {
+            module: naga::front::wgsl::parse_str(SHADER).unwrap(),
+            entry_point: "main".to_string(),
+        }
+    }
+
+    fn declared(size: u64) -> DeclaredBuffers {
+        DeclaredBuffers {
+            graph_path: PathBuf::from("scene.passes.toml"),
+            buffers: vec![("a".to_string(), 16), ("particles".to_string(), size)],
+        }
+    }
+
+    #[test]
+    fn buffers_that_fit_pass_and_the_graph_is_watched() {
+        let mut files = vec![];
+        let result = declared(128).check(Path::new("scene.wgsl"), &[&stage()], &mut files);
+        assert!(result.is_ok());
+        assert_eq!(files, [PathBuf::from("scene.passes.toml")]);
+    }
+
+    #[test]
+    fn small_buffers_are_rejected() {
+        let error = declared(64)
+            .check(Path::new("scene.wgsl"), &[&stage()], &mut vec![])
+            .unwrap_err();
+        assert!(error
+            .message
+            .contains("`particles` at @group(3) @binding(1)"));
+        assert!(error.message.contains("128 bytes, but particles"));
+    }
+
+    #[test]
+    fn the_placeholder_is_too_small_for_undeclared_buffers() {
+        let storage = DeclaredBuffers::default();
+        let error = storage
+            .check(Path::new("scene.wgsl"), &[&stage()], &mut vec![])
+            .unwrap_err();
+        assert!(error.message.contains("declares no storage buffer for it"));
+    }
+}
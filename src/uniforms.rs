@@ -0,0 +1,584 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem::discriminant,
+    ops::RangeInclusive,
+};
+
+use naga::{AddressSpace, ResourceBinding, ScalarKind, TypeInner, VectorSize};
+use serde::{Deserialize, Serialize};
+
+/// Buffers are never smaller than this, so a shader without a uniform still
+/// gets something valid to bind.
+const MIN_BUFFER_SIZE: u32 = 16;
+
+/// The value behind one reflected uniform member. Saved with the session as
+/// e.g. `{ vec2 = [0.5, 1.0] }`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UniformValue {
+    F32(f32),
+    I32(i32),
+    U32(u32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    /// Anything without a control (matrices, arrays, nested structs). It's
+    /// left zeroed in the buffer.
+    Unsupported(String),
+}
+
+impl UniformValue {
+    fn from_type(inner: &TypeInner) -> Self {
+        match *inner {
+            TypeInner::Scalar {
+                kind: ScalarKind::Float,
+                width: 4,
+            } => Self::F32(0.0),
+            TypeInner::Scalar {
+                kind: ScalarKind::Sint,
+                width: 4,
+            } => Self::I32(0),
+            TypeInner::Scalar {
+                kind: ScalarKind::Uint,
+                width: 4,
+            } => Self::U32(0),
+            TypeInner::Vector {
+                size,
+                kind: ScalarKind::Float,
+                width: 4,
+            } => match size {
+                VectorSize::Bi => Self::Vec2([0.0; 2]),
+                VectorSize::Tri => Self::Vec3([1.0; 3]),
+                VectorSize::Quad => Self::Vec4([1.0; 4]),
+            },
+            ref other => Self::Unsupported(format!("{other:?}")),
+        }
+    }
+
+    fn is_integer(&self) -> bool {
+        matches!(self, Self::I32(_) | Self::U32(_))
+    }
+
+    /// Overwrite the value from a list of numbers, one per component.
+    fn set(&mut self, numbers: &[f64]) -> Result<(), String> {
+        let expected = match self {
+            Self::F32(_) | Self::I32(_) | Self::U32(_) => 1,
+            Self::Vec2(_) => 2,
+            Self::Vec3(_) => 3,
+            Self::Vec4(_) => 4,
+            Self::Unsupported(_) => return Err("this type has no default".to_owned()),
+        };
+        if numbers.len() != expected {
+            return Err(format!(
+                "expected {expected} default value(s), got {}",
+                numbers.len()
+            ));
+        }
+        match self {
+            Self::F32(x) => *x = numbers[0] as f32,
+            Self::I32(x) => *x = numbers[0] as i32,
+            Self::U32(x) => *x = numbers[0].max(0.0) as u32,
+            Self::Vec2(v) => v.iter_mut().zip(numbers).for_each(|(x, n)| *x = *n as f32),
+            Self::Vec3(v) => v.iter_mut().zip(numbers).for_each(|(x, n)| *x = *n as f32),
+            Self::Vec4(v) => v.iter_mut().zip(numbers).for_each(|(x, n)| *x = *n as f32),
+            Self::Unsupported(_) => {}
+        }
+        Ok(())
+    }
+
+    fn write(&self, bytes: &mut [u8]) {
+        match self {
+            Self::F32(x) => bytes[..4].copy_from_slice(bytemuck::bytes_of(x)),
+            Self::I32(x) => bytes[..4].copy_from_slice(bytemuck::bytes_of(x)),
+            Self::U32(x) => bytes[..4].copy_from_slice(bytemuck::bytes_of(x)),
+            Self::Vec2(x) => bytes[..8].copy_from_slice(bytemuck::cast_slice(x)),
+            Self::Vec3(x) => bytes[..12].copy_from_slice(bytemuck::cast_slice(x)),
+            Self::Vec4(x) => bytes[..16].copy_from_slice(bytemuck::cast_slice(x)),
+            Self::Unsupported(_) => {}
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Widget {
+    Slider,
+    Drag,
+    Color,
+    Checkbox,
+}
+
+impl Widget {
+    /// What a member gets without an annotation. WGSL doesn't allow `bool` in
+    /// uniform buffers, but a `u32` is as likely to be a count as a flag, so it
+    /// only becomes a checkbox when annotated as one.
+    fn default_for(value: &UniformValue) -> Self {
+        match value {
+            UniformValue::F32(_) => Self::Slider,
+            UniformValue::Vec3(_) | UniformValue::Vec4(_) => Self::Color,
+            _ => Self::Drag,
+        }
+    }
+
+    fn supports(self, value: &UniformValue) -> bool {
+        match self {
+            Self::Slider | Self::Drag => !matches!(value, UniformValue::Unsupported(_)),
+            Self::Color => matches!(value, UniformValue::Vec3(_) | UniformValue::Vec4(_)),
+            Self::Checkbox => value.is_integer(),
+        }
+    }
+}
+
+/// How a member is edited in the UI.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Control {
+    pub widget: Widget,
+    /// Slider bounds, or the clamp range of a drag value.
+    pub range: Option<RangeInclusive<f64>>,
+    /// Drag speed.
+    pub step: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct UniformField {
+    pub name: String,
+    pub offset: u32,
+    pub control: Control,
+    pub value: UniformValue,
+}
+
+/// The uniform a shader reads at `@group(0) @binding(0)`, reflected from its
+/// naga module. A struct gets one field per member; anything else is a
+/// single field named after the variable.
+///
+/// Struct members can be annotated with a comment on the line above:
+///
+/// ```wgsl
+/// struct Params {
+///     // @ui slider min=0 max=10 default=1
+///     scale: f32,
+///     // @ui color default=0,0,1,1
+///     color: vec4<f32>,
+/// };
+/// ```
+///
+/// The first word picks the widget (`slider`, `drag`, `color` or
+/// `checkbox`); `min`, `max`, `step` and `default` can follow in any order.
+/// Members of a GLSL uniform block are annotated the same way.
+#[derive(Clone, Debug, Default)]
+pub struct UniformBlock {
+    pub fields: Vec<UniformField>,
+    pub size: u32,
+    /// Annotations that couldn't be applied.
+    pub warnings: Vec<String>,
+}
+
+impl UniformBlock {
+    pub fn reflect(module: &naga::Module, source: &str) -> Self {
+        let binding = ResourceBinding {
+            group: 0,
+            binding: 0,
+        };
+        Self::reflect_binding(module, source, binding)
+    }
+
+    /// Like `reflect`, for the uniform at `binding` instead.
+    pub fn reflect_binding(module: &naga::Module, source: &str, binding: ResourceBinding) -> Self {
+        let Some(var) = module
+            .global_variables
+            .iter()
+            .map(|(_, var)| var)
+            .find(|var| var.space == AddressSpace::Uniform && var.binding == Some(binding.clone()))
+        else {
+            return Self::default();
+        };
+
+        let ty = &module.types[var.ty];
+        let mut warnings = vec![];
+        let fields = match &ty.inner {
+            TypeInner::Struct { members, .. } => {
+                let annotations = ty
+                    .name
+                    .as_deref()
+                    .map(|name| find_annotations(source, name, &mut warnings))
+                    .unwrap_or_default();
+                members
+                    .iter()
+                    .map(|member| {
+                        let name = member.name.clone().unwrap_or_default();
+                        let value = UniformValue::from_type(&module.types[member.ty].inner);
+                        let annotation = annotations.get(&name);
+                        UniformField::new(name, member.offset, value, annotation, &mut warnings)
+                    })
+                    .collect()
+            }
+            other => vec![UniformField::new(
+                var.name.clone().unwrap_or_default(),
+                0,
+                UniformValue::from_type(other),
+                None,
+                &mut warnings,
+            )],
+        };
+        Self {
+            fields,
+            size: ty.inner.size(&module.constants),
+            warnings,
+        }
+    }
+
+    /// Whether both blocks have the same members at the same offsets with the
+    /// same controls, so only the values differ.
+    pub fn layout_matches(&self, other: &Self) -> bool {
+        self.size == other.size
+            && self.warnings == other.warnings
+            && self.fields.len() == other.fields.len()
+            && self.fields.iter().zip(&other.fields).all(|(a, b)| {
+                a.name == b.name
+                    && a.offset == b.offset
+                    && a.control == b.control
+                    && discriminant(&a.value) == discriminant(&b.value)
+            })
+    }
+
+    /// A block with just `values`, for `with_values_from` to carry over into
+    /// the layout reflected from a shader.
+    pub fn from_values(values: impl IntoIterator<Item = (String, UniformValue)>) -> Self {
+        let fields = values
+            .into_iter()
+            .map(|(name, value)| UniformField {
+                name,
+                offset: 0,
+                control: Control {
+                    widget: Widget::default_for(&value),
+                    range: None,
+                    step: 0.01,
+                },
+                value,
+            })
+            .collect();
+        Self {
+            fields,
+            ..Self::default()
+        }
+    }
+
+    /// The values that have a control, by member name.
+    pub fn values(&self) -> BTreeMap<String, UniformValue> {
+        self.fields
+            .iter()
+            .filter(|field| !matches!(field.value, UniformValue::Unsupported(_)))
+            .map(|field| (field.name.clone(), field.value.clone()))
+            .collect()
+    }
+
+    /// This layout, with values carried over from `old` wherever a member
+    /// kept its name and type.
+    pub fn with_values_from(&self, old: &Self) -> Self {
+        let mut block = self.clone();
+        for field in &mut block.fields {
+            let previous = old.fields.iter().find(|old| {
+                old.name == field.name && discriminant(&old.value) == discriminant(&field.value)
+            });
+            if let Some(previous) = previous {
+                field.value = previous.value.clone();
+            }
+        }
+        block
+    }
+
+    pub fn buffer_size(&self) -> u32 {
+        self.size.max(MIN_BUFFER_SIZE)
+    }
+
+    /// The values packed at their reflected offsets, padded to
+    /// `buffer_size`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; self.buffer_size() as usize];
+        for field in &self.fields {
+            field.value.write(&mut bytes[field.offset as usize..]);
+        }
+        bytes
+    }
+}
+
+impl UniformField {
+    fn new(
+        name: String,
+        offset: u32,
+        mut value: UniformValue,
+        annotation: Option<&(u32, String)>,
+        warnings: &mut Vec<String>,
+    ) -> Self {
+        let mut control = Control {
+            widget: Widget::default_for(&value),
+            range: None,
+            step: if value.is_integer() { 0.1 } else { 0.01 },
+        };
+
+        let (mut min, mut max) = (None, None);
+        if let Some((line, text)) = annotation {
+            let mut warn = |message: String| warnings.push(format!("line {line}: {message}"));
+            for token in text.split_whitespace() {
+                let Some((key, arg)) = token.split_once('=') else {
+                    let widget = match token {
+                        "slider" => Widget::Slider,
+                        "drag" => Widget::Drag,
+                        "color" => Widget::Color,
+                        "checkbox" => Widget::Checkbox,
+                        _ => {
+                            warn(format!("unknown widget `{token}`"));
+                            continue;
+                        }
+                    };
+                    if widget.supports(&value) {
+                        control.widget = widget;
+                    } else {
+                        warn(format!("`{token}` doesn't work for `{name}`"));
+                    }
+                    continue;
+                };
+
+                let numbers: Result<Vec<f64>, _> = arg.split(',').map(str::parse).collect();
+                let Ok(numbers) = numbers else {
+                    warn(format!("`{arg}` isn't a number for `{key}`"));
+                    continue;
+                };
+                match (key, numbers.as_slice()) {
+                    ("min", &[x]) => min = Some(x),
+                    ("max", &[x]) => max = Some(x),
+                    ("step", &[step]) => control.step = step,
+                    ("default", numbers) => {
+                        if let Err(message) = value.set(numbers) {
+                            warn(message);
+                        }
+                    }
+                    ("min" | "max" | "step", _) => warn(format!("`{key}` takes one number")),
+                    _ => warn(format!("unknown key `{key}`")),
+                }
+            }
+        }
+
+        // Sliders need both ends, drag values can be open on either side.
+        let (low, high) = if value.is_integer() {
+            (0.0, 100.0)
+        } else {
+            (0.0, 1.0)
+        };
+        control.range = match (control.widget, min, max) {
+            (Widget::Slider, min, max) => Some(min.unwrap_or(low)..=max.unwrap_or(high)),
+            (_, None, None) => None,
+            (_, min, max) => Some(min.unwrap_or(f64::NEG_INFINITY)..=max.unwrap_or(f64::INFINITY)),
+        };
+
+        Self {
+            name,
+            offset,
+            control,
+            value,
+        }
+    }
+}
+
+/// Find `// @ui` comments inside `struct <name> { ... }` and pair each with
+/// the member on the line below. Returns the annotation text and its 1-based
+/// line, keyed by member name.
+fn find_annotations(
+    source: &str,
+    struct_name: &str,
+    warnings: &mut Vec<String>,
+) -> HashMap<String, (u32, String)> {
+    let mut annotations = HashMap::new();
+    let mut lines = (1..).zip(source.lines());
+    // `struct Params` in WGSL, `layout(...) uniform Params` in GLSL.
+    let is_start = |line: &str| {
+        let line = line.trim_start();
+        line.strip_prefix("struct")
+            .or_else(|| line.split_once("uniform ").map(|(_, rest)| rest))
+            .and_then(|rest| rest.trim_start().strip_prefix(struct_name))
+            .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+    };
+    if !lines.any(|(_, line)| is_start(line)) {
+        return annotations;
+    }
+
+    let mut pending: Option<(u32, String)> = None;
+    for (number, line) in lines {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix("//") {
+            if let Some(text) = comment.trim().strip_prefix("@ui") {
+                if let Some((orphan, _)) = pending.replace((number, text.trim().to_owned())) {
+                    warnings.push(format!("line {orphan}: annotation isn't above a member"));
+                }
+            }
+            continue;
+        }
+        let code = line.split("//").next().unwrap_or_default();
+        if let Some(member) = member_name(code) {
+            if let Some(annotation) = pending.take() {
+                annotations.insert(member.to_owned(), annotation);
+            }
+        }
+        if code.contains('}') {
+            break;
+        }
+    }
+    if let Some((orphan, _)) = pending {
+        warnings.push(format!("line {orphan}: annotation isn't above a member"));
+    }
+    annotations
+}
+
+/// The member name in a line like `@align(16) name: type,`, or `type name;`
+/// in GLSL.
+fn member_name(mut code: &str) -> Option<&str> {
+    // Skip attributes.
+    while let Some(rest) = code.trim_start().strip_prefix('@') {
+        let end = match (rest.find('('), rest.find(char::is_whitespace)) {
+            (Some(paren), Some(space)) if paren < space => rest.find(')')? + 1,
+            (_, Some(space)) => space,
+            _ => return None,
+        };
+        code = &rest[end..];
+    }
+    let name = match code.split_once(':') {
+        Some((name, _)) => name.trim(),
+        None => {
+            let declaration = code.split_once(';')?.0;
+            let mut words = declaration.split_whitespace();
+            let name = words.next_back()?;
+            words.next()?;
+            name.split('[').next()?
+        }
+    };
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    valid.then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reflect(source: &str) -> UniformBlock {
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        UniformBlock::reflect(&module, source)
+    }
+
+    fn field<'a>(block: &'a UniformBlock, name: &str) -> &'a UniformField {
+        block
+            .fields
+            .iter()
+            .find(|field| field.name == name)
+            .unwrap()
+    }
+
+    const SHADER: &str = "
+struct Params {
+    // @ui slider min=1 max=10 default=2.5
+    scale: f32,
+    // @ui color default=0,0,1,1
+    tint: vec4<f32>,
+    count: u32,
+    // @ui checkbox default=1
+    enabled: u32,
+    @align(16) offset: vec2<f32>, // @ui ignored, it's not above a member
+};
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return params.tint * params.scale;
+}
+";
+
+    #[test]
+    fn annotations_set_widgets_ranges_and_defaults() {
+        let block = reflect(SHADER);
+        assert!(block.warnings.is_empty(), "{:?}", block.warnings);
+
+        let scale = field(&block, "scale");
+        assert_eq!(scale.control.widget, Widget::Slider);
+        assert_eq!(scale.control.range, Some(1.0..=10.0));
+        assert_eq!(scale.value, UniformValue::F32(2.5));
+
+        let tint = field(&block, "tint");
+        assert_eq!(tint.control.widget, Widget::Color);
+        assert_eq!(tint.value, UniformValue::Vec4([0.0, 0.0, 1.0, 1.0]));
+
+        let enabled = field(&block, "enabled");
+        assert_eq!(enabled.control.widget, Widget::Checkbox);
+        assert_eq!(enabled.value, UniformValue::U32(1));
+    }
+
+    #[test]
+    fn unannotated_members_get_default_widgets() {
+        let block = reflect(SHADER);
+        assert_eq!(field(&block, "count").control.widget, Widget::Drag);
+        assert_eq!(field(&block, "count").control.range, None);
+        assert_eq!(field(&block, "offset").control.widget, Widget::Drag);
+    }
+
+    #[test]
+    fn values_are_written_at_their_offsets() {
+        let block = reflect(SHADER);
+        let bytes = block.to_bytes();
+        assert_eq!(bytes.len(), block.size as usize);
+        let offset = field(&block, "scale").offset as usize;
+        assert_eq!(bytes[offset..offset + 4], 2.5f32.to_ne_bytes());
+    }
+
+    #[test]
+    fn malformed_annotations_warn() {
+        let block = reflect(
+            "
+struct Params {
+    // @ui knob
+    a: f32,
+    // @ui color
+    b: f32,
+    // @ui min=low
+    c: f32,
+    // @ui min=1,2 default=1,2
+    d: f32,
+    // @ui speed=3
+    e: f32,
+    // @ui slider
+
+    // @ui drag
+    f: f32,
+};
+
+// @ui slider
+@group(0) @binding(0)
+var<uniform> params: Params;
+",
+        );
+        assert_eq!(
+            block.warnings,
+            [
+                "line 13: annotation isn't above a member",
+                "line 3: unknown widget `knob`",
+                "line 5: `color` doesn't work for `b`",
+                "line 7: `low` isn't a number for `min`",
+                "line 9: `min` takes one number",
+                "line 9: expected 1 default value(s), got 2",
+                "line 11: unknown key `speed`",
+            ]
+        );
+        assert_eq!(field(&block, "f").control.widget, Widget::Drag);
+    }
+
+    #[test]
+    fn member_names_in_wgsl_and_glsl() {
+        assert_eq!(member_name("scale: f32,"), Some("scale"));
+        assert_eq!(
+            member_name("@align(16) @size(32) tint: vec4<f32>,"),
+            Some("tint")
+        );
+        assert_eq!(member_name("float weights[4];"), Some("weights"));
+        assert_eq!(member_name("highp vec2 offset;"), Some("offset"));
+        assert_eq!(member_name("};"), None);
+        assert_eq!(member_name(""), None);
+    }
+}
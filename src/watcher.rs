@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use color_eyre::eyre::Result;
+use notify::{PollWatcher, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer_opt, DebounceEventResult, Debouncer};
+
+/// What to watch and how.
+#[derive(Clone, Debug)]
+pub struct WatchConfig {
+    /// Watched recursively.
+    pub dirs: Vec<PathBuf>,
+    /// How long a file has to be quiet before its change is reported.
+    pub debounce: Duration,
+    /// Poll every `poll_interval` instead of using the platform's file events.
+    /// Needed on network mounts and in some containers, where events never
+    /// arrive.
+    pub poll: bool,
+    pub poll_interval: Duration,
+}
+
+/// Watches shader directories and sends debounced changes to a channel until
+/// dropped.
+pub enum ShaderWatcher {
+    Native(Debouncer<RecommendedWatcher>),
+    Poll(Debouncer<PollWatcher>),
+}
+
+impl ShaderWatcher {
+    /// Start watching. Uses the platform's recommended backend unless polling
+    /// was asked for, and falls back to polling if the native backend can't
+    /// watch the directories (e.g. when out of inotify watches).
+    pub fn new(config: &WatchConfig, sender: Sender<DebounceEventResult>) -> Result<Self> {
+        if !config.poll {
+            let native = new_debouncer_opt(
+                config.debounce,
+                None,
+                sender.clone(),
+                notify::Config::default(),
+            )
+            .map_err(Into::into)
+            .map(Self::Native)
+            .and_then(|mut watcher| watcher.watch_all(&config.dirs).map(|()| watcher));
+            match native {
+                Ok(watcher) => return Ok(watcher),
+                Err(err) => eprintln!("Native file watching failed ({err}), polling instead."),
+            }
+        }
+        let notify_config = notify::Config::default().with_poll_interval(config.poll_interval);
+        let mut watcher = Self::Poll(new_debouncer_opt(
+            config.debounce,
+            None,
+            sender,
+            notify_config,
+        )?);
+        watcher.watch_all(&config.dirs)?;
+        Ok(watcher)
+    }
+
+    /// Also watch `dir`, recursively.
+    pub fn watch(&mut self, dir: &Path) -> Result<()> {
+        let watcher = match self {
+            Self::Native(debouncer) => debouncer.watcher(),
+            Self::Poll(debouncer) => debouncer.watcher(),
+        };
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+        Ok(())
+    }
+
+    fn watch_all(&mut self, dirs: &[PathBuf]) -> Result<()> {
+        dirs.iter().try_for_each(|dir| self.watch(dir))
+    }
+
+    pub fn is_polling(&self) -> bool {
+        matches!(self, Self::Poll(_))
+    }
+}
+
+/// Editor swap, backup and temp files, which shouldn't trigger a reload.
+pub fn is_temp_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return true;
+    };
+    // Vim writes `4913` to check it can create files in the directory.
+    name.starts_with('.')
+        || name.starts_with('#')
+        || name.ends_with('~')
+        || name == "4913"
+        || [".swp", ".swo", ".swx", ".tmp", ".bak", ".orig"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editor_temp_files_are_ignored() {
+        for path in [
+            "shaders/.shader.wgsl.swp",
+            "shaders/shader.wgsl.swx",
+            "shaders/shader.wgsl~",
+            "shaders/#shader.wgsl#",
+            "shaders/4913",
+            "shaders/shader.wgsl.tmp",
+            "shaders/shader.wgsl.orig",
+            "shaders/..",
+        ] {
+            assert!(is_temp_file(Path::new(path)), "{path} isn't a temp file");
+        }
+    }
+
+    #[test]
+    fn shader_files_are_not_temp_files() {
+        for path in [
+            "shaders/shader.wgsl",
+            "shader.frag",
+            "shaders/shader.passes.toml",
+            "shaders/49130.wgsl",
+            "shaders/swp.wgsl",
+        ] {
+            assert!(!is_temp_file(Path::new(path)), "{path} is a temp file");
+        }
+    }
+}